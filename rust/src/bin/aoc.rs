@@ -0,0 +1,44 @@
+use adventofcode2023::{leaderboard, profile::Profile, status};
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+
+#[derive(Parser)]
+struct Cli {
+    /// Profile whose session cookie and cache are used.
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show a private leaderboard (refreshed at most every 15 minutes).
+    Leaderboard { id: u64 },
+    /// Show which days have stars, a Rust solver and recorded answers.
+    Status,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let profile = match cli.profile.as_deref() {
+        Some(name) => Profile::named(name)?,
+        None => Profile::default_profile(),
+    };
+
+    match cli.command {
+        Command::Leaderboard { id } => {
+            let lb = leaderboard::fetch(&profile, id)?;
+            print!("{}", lb.render());
+        }
+        Command::Status => {
+            let calendar = status::fetch_calendar(&profile)?;
+            let solvers = status::rust_solvers()?;
+            let statuses = status::merge(&calendar, &solvers, |day| profile.recorded_answers(day));
+            print!("{}", status::render(&statuses));
+        }
+    }
+
+    Ok(())
+}
@@ -1,15 +1,22 @@
-use adventofcode2023::AocSolution;
+use adventofcode2023::{
+    export::{Export, ExportFile},
+    format_table, AocSolution,
+};
 
 use std::{
-    cell::RefCell,
-    collections::{HashMap, VecDeque},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fmt::{self, Write as _},
+    io::{self, BufWriter, Write as _},
 };
 
+use anyhow::{anyhow, bail, Context, Result};
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 
 pub struct Solution;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 enum Pulse {
     High,
     Low,
@@ -22,174 +29,593 @@ struct PulseSignal<'a> {
     pulse: Pulse,
 }
 
-#[derive(Debug)]
-enum Module<'a> {
-    Broadcaster {
-        output: Vec<&'a str>,
-    },
+/// What a module is, along with the state the puzzle's own kinds have.
+#[derive(Debug, Clone, Copy)]
+enum ModuleKind<'m, 'a> {
+    Broadcaster,
     FlipFlop {
-        name: &'a str,
-        output: Vec<&'a str>,
-        state: bool,
+        on: bool,
     },
     Conjunction {
-        name: &'a str,
-        output: Vec<&'a str>,
-        inputs: HashMap<String, Pulse>,
-    },
-    Untyped {
-        name: &'a str,
+        memory: &'m HashMap<&'a str, Pulse>,
     },
+    Sink,
+    /// A kind that isn't part of the puzzle, by name.
+    Other(&'static str),
 }
 
-impl<'a> Module<'a> {
-    fn parse(input: &'a str) -> (&'a str, Self) {
-        let (name, output_names) = input.split(" -> ").next_tuple().unwrap();
+impl fmt::Display for ModuleKind<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ModuleKind::Broadcaster => "broadcaster",
+            ModuleKind::FlipFlop { .. } => "flip-flop",
+            ModuleKind::Conjunction { .. } => "conjunction",
+            ModuleKind::Sink => "sink",
+            ModuleKind::Other(name) => name,
+        };
+        f.write_str(name)
+    }
+}
 
-        let output = output_names.split(", ").collect::<Vec<_>>();
+/// A kind of module of the network. On every pulse it receives, a module may send one pulse,
+/// which goes to all of its outputs.
+trait PulseModule<'a>: fmt::Debug {
+    fn name(&self) -> &'a str;
 
-        match name {
-            "broadcaster" => ("broadcaster", Module::Broadcaster { output }),
-            name if name.starts_with('%') => (
-                &name[1..],
-                Module::FlipFlop {
-                    name: &name[1..],
-                    output,
-                    state: false,
-                },
-            ),
-            name if name.starts_with('&') => (
-                &name[1..],
-                Module::Conjunction {
-                    name: &name[1..],
-                    inputs: HashMap::new(),
-                    output,
-                },
-            ),
-            _ => unreachable!(),
-        }
-    }
-
-    fn output_names(&self) -> &[&'a str] {
-        match self {
-            Module::Broadcaster { output } => output,
-            Module::FlipFlop { output, .. } => output,
-            Module::Conjunction { output, .. } => output,
-            Module::Untyped { .. } => &[],
+    fn outputs(&self) -> &[&'a str];
+
+    /// What kind of module this is, for error messages, analyses and compiling. Kinds that
+    /// aren't part of the puzzle are named after their type unless they say otherwise.
+    fn kind(&self) -> ModuleKind<'_, 'a> {
+        ModuleKind::Other(std::any::type_name::<Self>())
+    }
+
+    /// The prefix the module is declared with in the input.
+    fn prefix(&self) -> &'static str {
+        ""
+    }
+
+    /// Called while parsing for every module that has this one among its outputs.
+    fn connect(&mut self, _src: &'a str) {}
+
+    fn receive(&mut self, src: &'a str, pulse: Pulse) -> Option<Pulse>;
+
+    /// The internal state for snapshots, `None` for stateless modules.
+    fn state(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    /// Puts back the internal state returned by [`PulseModule::state`].
+    fn set_state(&mut self, _state: &serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
+    fn dot_shape(&self) -> &'static str {
+        "ellipse"
+    }
+
+    /// Shows the internal state in the Graphviz export.
+    fn dot_state(&self, _label: &mut String, _attrs: &mut Vec<String>) {}
+}
+
+#[derive(Debug)]
+struct Broadcaster<'a> {
+    outputs: Vec<&'a str>,
+}
+
+impl<'a> PulseModule<'a> for Broadcaster<'a> {
+    fn name(&self) -> &'a str {
+        "broadcaster"
+    }
+
+    fn outputs(&self) -> &[&'a str] {
+        &self.outputs
+    }
+
+    fn kind(&self) -> ModuleKind<'_, 'a> {
+        ModuleKind::Broadcaster
+    }
+
+    fn receive(&mut self, _src: &'a str, pulse: Pulse) -> Option<Pulse> {
+        Some(pulse)
+    }
+
+    fn dot_shape(&self) -> &'static str {
+        "house"
+    }
+}
+
+#[derive(Debug)]
+struct FlipFlop<'a> {
+    name: &'a str,
+    outputs: Vec<&'a str>,
+    on: bool,
+}
+
+impl<'a> PulseModule<'a> for FlipFlop<'a> {
+    fn name(&self) -> &'a str {
+        self.name
+    }
+
+    fn outputs(&self) -> &[&'a str] {
+        &self.outputs
+    }
+
+    fn kind(&self) -> ModuleKind<'_, 'a> {
+        ModuleKind::FlipFlop { on: self.on }
+    }
+
+    fn prefix(&self) -> &'static str {
+        "%"
+    }
+
+    fn receive(&mut self, _src: &'a str, pulse: Pulse) -> Option<Pulse> {
+        if let Pulse::High = pulse {
+            None
+        } else {
+            self.on = !self.on;
+            Some(if self.on { Pulse::High } else { Pulse::Low })
         }
     }
 
-    fn receive(&mut self, source: &'a str, pulse: Pulse) -> Option<Vec<PulseSignal<'a>>> {
-        match self {
-            Module::Broadcaster { output } => Some(
-                output
-                    .iter()
-                    .map(|dst| PulseSignal {
-                        src: "broadcaster",
-                        dst,
-                        pulse,
-                    })
-                    .collect(),
-            ),
-            Module::FlipFlop {
+    fn state(&self) -> Option<serde_json::Value> {
+        Some(self.on.into())
+    }
+
+    fn set_state(&mut self, state: &serde_json::Value) -> Result<()> {
+        self.on = serde_json::from_value(state.clone())?;
+        Ok(())
+    }
+
+    fn dot_shape(&self) -> &'static str {
+        "box"
+    }
+
+    fn dot_state(&self, _label: &mut String, attrs: &mut Vec<String>) {
+        attrs.push("style=filled".to_string());
+        let color = if self.on { "palegreen" } else { "white" };
+        attrs.push(format!("fillcolor={color}"));
+    }
+}
+
+#[derive(Debug)]
+struct Conjunction<'a> {
+    name: &'a str,
+    outputs: Vec<&'a str>,
+    /// The last pulse received from each input.
+    memory: HashMap<&'a str, Pulse>,
+}
+
+impl<'a> PulseModule<'a> for Conjunction<'a> {
+    fn name(&self) -> &'a str {
+        self.name
+    }
+
+    fn outputs(&self) -> &[&'a str] {
+        &self.outputs
+    }
+
+    fn kind(&self) -> ModuleKind<'_, 'a> {
+        ModuleKind::Conjunction {
+            memory: &self.memory,
+        }
+    }
+
+    fn prefix(&self) -> &'static str {
+        "&"
+    }
+
+    fn connect(&mut self, src: &'a str) {
+        self.memory.insert(src, Pulse::Low);
+    }
+
+    fn receive(&mut self, src: &'a str, pulse: Pulse) -> Option<Pulse> {
+        self.memory.insert(src, pulse);
+        if self
+            .memory
+            .values()
+            .all(|pulse| matches!(pulse, Pulse::High))
+        {
+            Some(Pulse::Low)
+        } else {
+            Some(Pulse::High)
+        }
+    }
+
+    fn state(&self) -> Option<serde_json::Value> {
+        let memory = self.memory.iter().collect::<BTreeMap<_, _>>();
+        Some(serde_json::to_value(memory).unwrap())
+    }
+
+    fn set_state(&mut self, state: &serde_json::Value) -> Result<()> {
+        let memory = serde_json::from_value::<BTreeMap<String, Pulse>>(state.clone())?;
+        if memory.len() != self.memory.len() {
+            bail!(
+                "{} has {} inputs, not {}",
+                self.name,
+                self.memory.len(),
+                memory.len()
+            );
+        }
+        for (input, pulse) in self.memory.iter_mut() {
+            *pulse = *memory
+                .get(*input)
+                .ok_or_else(|| anyhow!("no memory of {input} for {}", self.name))?;
+        }
+        Ok(())
+    }
+
+    fn dot_shape(&self) -> &'static str {
+        "invtrapezium"
+    }
+
+    fn dot_state(&self, label: &mut String, _attrs: &mut Vec<String>) {
+        for (input, pulse) in self.memory.iter().sorted_by_key(|(k, _)| **k) {
+            let pulse = if matches!(pulse, Pulse::High) {
+                "H"
+            } else {
+                "L"
+            };
+            *label += &format!("\\n{input}={pulse}");
+        }
+    }
+}
+
+/// A destination that isn't declared in the input, like `rx`.
+#[derive(Debug)]
+struct Sink<'a> {
+    name: &'a str,
+}
+
+impl<'a> PulseModule<'a> for Sink<'a> {
+    fn name(&self) -> &'a str {
+        self.name
+    }
+
+    fn outputs(&self) -> &[&'a str] {
+        &[]
+    }
+
+    fn kind(&self) -> ModuleKind<'_, 'a> {
+        ModuleKind::Sink
+    }
+
+    fn receive(&mut self, _src: &'a str, _pulse: Pulse) -> Option<Pulse> {
+        None
+    }
+}
+
+/// Builds a module from its name, without the prefix, and its outputs.
+type ModuleParser = for<'a> fn(&'a str, Vec<&'a str>) -> Box<dyn PulseModule<'a> + 'a>;
+
+/// The module kinds the parser knows about, by the prefix of their names.
+struct Registry {
+    parsers: Vec<(&'static str, ModuleParser)>,
+}
+
+impl Registry {
+    /// Flip-flops and conjunctions, the kinds of the puzzle.
+    fn standard() -> Self {
+        let mut registry = Self {
+            parsers: Vec::new(),
+        };
+        registry.register("%", |name, outputs| {
+            Box::new(FlipFlop {
                 name,
-                output,
-                state,
-            } => {
-                if let Pulse::High = pulse {
-                    None
-                } else {
-                    *state = !*state;
-                    let pulse = if *state { Pulse::High } else { Pulse::Low };
-                    Some(
-                        output
-                            .iter()
-                            .map(|dst| PulseSignal {
-                                src: name,
-                                dst,
-                                pulse,
-                            })
-                            .collect(),
-                    )
-                }
-            }
-            Module::Conjunction {
+                outputs,
+                on: false,
+            })
+        });
+        registry.register("&", |name, outputs| {
+            Box::new(Conjunction {
                 name,
-                output,
-                inputs,
-            } => {
-                inputs.insert(source.to_string(), pulse);
-                let pulse = if inputs.values().all(|pulse| matches!(pulse, Pulse::High)) {
-                    Pulse::Low
-                } else {
-                    Pulse::High
-                };
+                outputs,
+                memory: HashMap::new(),
+            })
+        });
+        registry
+    }
 
-                Some(
-                    output
-                        .iter()
-                        .map(|dst| PulseSignal {
-                            src: name,
-                            dst,
-                            pulse,
-                        })
-                        .collect(),
-                )
-            }
-            Module::Untyped { name: _ } => None,
+    /// Adds a module kind, replacing the one that used the same prefix.
+    fn register(&mut self, prefix: &'static str, parser: ModuleParser) {
+        assert!(!prefix.is_empty(), "module prefixes can't be empty");
+        self.parsers.retain(|(p, _)| *p != prefix);
+        self.parsers.push((prefix, parser));
+    }
+
+    fn parse<'a>(&self, line: &'a str) -> Result<Box<dyn PulseModule<'a> + 'a>> {
+        let Some((name, outputs)) = line.split_once(" -> ") else {
+            bail!("no ` -> ` in {line:?}");
+        };
+        let outputs = outputs.split(", ").collect_vec();
+
+        if name == "broadcaster" {
+            return Ok(Box::new(Broadcaster { outputs }));
         }
+
+        // The longest prefix wins, so that `%` and `%%` can be different kinds.
+        let (prefix, parser) = self
+            .parsers
+            .iter()
+            .filter(|(prefix, _)| name.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .ok_or_else(|| anyhow!("unknown module kind in {line:?}"))?;
+        Ok(parser(&name[prefix.len()..], outputs))
     }
 }
 
 #[derive(Debug)]
 struct ModuleMap<'a> {
-    modules: RefCell<HashMap<String, Module<'a>>>,
+    modules: HashMap<&'a str, Box<dyn PulseModule<'a> + 'a>>,
+    /// Module names in the order they appear in the input.
+    order: Vec<&'a str>,
     count_high: u64,
     count_low: u64,
     count_rx: u64,
+    /// Module whose incoming high pulses are recorded in `high_into_watched` on every press.
+    watched: Option<&'a str>,
+    high_into_watched: Vec<&'a str>,
+    /// Number of times the button has been pressed.
+    presses: u64,
+    trace: Option<Trace<'a>>,
+}
+
+/// One pulse, as seen by the trace recorder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct TraceEvent<'a> {
+    /// The button press this pulse belongs to, starting at 1.
+    press: u64,
+    /// Position of the pulse within its press, the button's own pulse being 0.
+    seq: u64,
+    src: &'a str,
+    dst: &'a str,
+    pulse: Pulse,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct PulseCounts {
+    high: u64,
+    low: u64,
+}
+
+/// Records every pulse sent while it is attached to a [`ModuleMap`], either in memory or as
+/// JSON lines in a file, see the `trace` export. Only traces in memory can be queried: a file
+/// trace has to be read back with [`Trace::parse_jsonl`] first, as `--from` does.
+#[derive(Debug)]
+enum Trace<'a> {
+    Memory(Vec<TraceEvent<'a>>),
+    File {
+        writer: BufWriter<ExportFile>,
+        /// The first write error, reported by [`Trace::finish`].
+        error: Option<io::Error>,
+    },
+}
+
+impl<'a> Trace<'a> {
+    fn in_memory() -> Self {
+        Trace::Memory(Vec::new())
+    }
+
+    fn to_file(file: ExportFile) -> Self {
+        Trace::File {
+            writer: BufWriter::new(file),
+            error: None,
+        }
+    }
+
+    fn parse_jsonl(text: &'a str) -> Result<Self> {
+        let events = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| serde_json::from_str(line).map_err(|e| anyhow!("line {}: {e}", i + 1)))
+            .collect::<Result<_>>()?;
+        Ok(Trace::Memory(events))
+    }
+
+    fn record(&mut self, event: TraceEvent<'a>) {
+        match self {
+            Trace::Memory(events) => events.push(event),
+            Trace::File { writer, error } => {
+                if error.is_none() {
+                    let result = serde_json::to_writer(&mut *writer, &event)
+                        .map_err(io::Error::from)
+                        .and_then(|_| writeln!(writer));
+                    *error = result.err();
+                }
+            }
+        }
+    }
+
+    /// Flushes a file trace, reporting any error that happened while recording.
+    fn finish(self) -> Result<Self> {
+        if let Trace::File { mut writer, error } = self {
+            if let Some(e) = error {
+                return Err(e.into());
+            }
+            writer.flush()?;
+            return Ok(Trace::File {
+                writer,
+                error: None,
+            });
+        }
+        Ok(self)
+    }
+
+    /// The recorded events, an error for a file trace.
+    fn events(&self) -> Result<&[TraceEvent<'a>]> {
+        match self {
+            Trace::Memory(events) => Ok(events),
+            Trace::File { .. } => bail!("a trace written to a file can't be queried"),
+        }
+    }
+
+    /// Every pulse of the given press, in the order they were sent.
+    fn press(&self, press: u64) -> Result<impl Iterator<Item = &TraceEvent<'a>>> {
+        Ok(self.events()?.iter().filter(move |e| e.press == press))
+    }
+
+    /// The first press during which `module` sends a `pulse`.
+    fn first_press_sending(&self, module: &str, pulse: Pulse) -> Result<Option<u64>> {
+        Ok(self
+            .events()?
+            .iter()
+            .find(|e| e.src == module && e.pulse == pulse)
+            .map(|e| e.press))
+    }
+
+    /// How many pulses each module sent, by kind. The button counts as a module.
+    fn pulses_per_module(&self) -> Result<BTreeMap<&'a str, PulseCounts>> {
+        let mut counts = BTreeMap::<_, PulseCounts>::new();
+        for e in self.events()? {
+            let entry = counts.entry(e.src).or_default();
+            match e.pulse {
+                Pulse::High => entry.high += 1,
+                Pulse::Low => entry.low += 1,
+            }
+        }
+        Ok(counts)
+    }
+}
+
+/// The internal state of every module of a network, along with the press and
+/// pulse counters, to go back to a known point of a simulation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Snapshot {
+    states: BTreeMap<String, serde_json::Value>,
+    presses: u64,
+    count_high: u64,
+    count_low: u64,
+}
+
+/// A table of the pulses each module sent, and the first press it sent each kind in.
+fn render_pulses(trace: &Trace) -> Result<String> {
+    let first = |module, pulse| -> Result<String> {
+        Ok(trace
+            .first_press_sending(module, pulse)?
+            .map(|press| press.to_string())
+            .unwrap_or_default())
+    };
+    let rows = trace
+        .pulses_per_module()?
+        .into_iter()
+        .map(|(module, counts)| {
+            Ok([
+                module.to_string(),
+                counts.high.to_string(),
+                counts.low.to_string(),
+                first(module, Pulse::High)?,
+                first(module, Pulse::Low)?,
+            ])
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(format_table(
+        &["Module", "High", "Low", "First high", "First low"],
+        &rows,
+    ))
+}
+
+/// The pulses of one press, in the notation of the puzzle, like `button -low-> broadcaster`.
+fn render_press(trace: &Trace, press: u64) -> Result<String> {
+    let mut out = String::new();
+    for e in trace.press(press)? {
+        let pulse = match e.pulse {
+            Pulse::High => "high",
+            Pulse::Low => "low",
+        };
+        writeln!(out, "{} -{pulse}-> {}", e.src, e.dst).unwrap();
+    }
+    Ok(out)
+}
+
+/// When one of the inputs of the conjunction feeding `rx` sends it a high pulse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InputCycle<'a> {
+    input: &'a str,
+    /// Every module upstream of `input`, up to the broadcaster.
+    subcircuit: Vec<&'a str>,
+    first: u64,
+    period: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CycleAnalysis<'a> {
+    feeder: &'a str,
+    cycles: Vec<InputCycle<'a>>,
+    presses: u64,
+}
+
+/// Smallest `x >= 0` with `x = r1 (mod m1)` and `x = r2 (mod m2)`, as `(x, lcm(m1, m2))`.
+fn crt((r1, m1): (i128, i128), (r2, m2): (i128, i128)) -> Option<(i128, i128)> {
+    fn ext_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+        if b == 0 {
+            (a, 1, 0)
+        } else {
+            let (g, x, y) = ext_gcd(b, a % b);
+            (g, y, x - (a / b) * y)
+        }
+    }
+
+    let (g, p, _) = ext_gcd(m1, m2);
+    if (r2 - r1) % g != 0 {
+        return None;
+    }
+
+    let lcm = m1 / g * m2;
+    let k = ((r2 - r1) / g * p).rem_euclid(m2 / g);
+    Some(((r1 + m1 * k).rem_euclid(lcm), lcm))
 }
 
 impl<'a> ModuleMap<'a> {
     fn parse(input: &'a str) -> Self {
-        let mut module_map = input
+        Self::parse_with(input, &Registry::standard()).unwrap()
+    }
+
+    fn parse_with(input: &'a str, registry: &Registry) -> Result<Self> {
+        let modules = input
             .trim()
             .lines()
-            .map(Module::parse)
-            .map(|(k, v)| (k.to_string(), v))
+            .map(|line| registry.parse(line))
+            .collect::<Result<Vec<_>>>()?;
+        let order = modules.iter().map(|m| m.name()).collect_vec();
+        let mut module_map = modules
+            .into_iter()
+            .map(|m| (m.name(), m))
             .collect::<HashMap<_, _>>();
 
-        let input_names = module_map
+        let edges = order
             .iter()
-            .flat_map(|(name, module)| {
-                module.output_names().iter().filter_map(|&dst_name| {
-                    if matches!(module_map.get(dst_name), Some(Module::Conjunction { .. })) {
-                        Some((name.to_string(), dst_name.to_string()))
-                    } else {
-                        None
-                    }
-                })
+            .flat_map(|name| {
+                module_map[*name]
+                    .outputs()
+                    .iter()
+                    .map(move |dst| (*name, *dst))
             })
             .collect_vec();
-
-        for (input_name, conj_name) in input_names {
-            if let Some(Module::Conjunction { inputs, .. }) = module_map.get_mut(conj_name.as_str())
-            {
-                inputs.insert(input_name, Pulse::Low);
-            } else {
-                panic!();
+        for (src, dst) in edges {
+            if let Some(module) = module_map.get_mut(dst) {
+                module.connect(src);
             }
         }
 
-        Self {
-            modules: RefCell::new(module_map),
+        Ok(Self {
+            modules: module_map,
+            order,
             count_low: 0,
             count_high: 0,
             count_rx: 0,
-        }
+            watched: None,
+            high_into_watched: Vec::new(),
+            presses: 0,
+            trace: None,
+        })
     }
 
     fn push_button(&mut self) {
         self.count_rx = 0;
+        self.high_into_watched.clear();
+        self.presses += 1;
+        let mut seq = 0;
 
         let mut q = VecDeque::new();
         q.push_front(PulseSignal {
@@ -199,8 +625,18 @@ impl<'a> ModuleMap<'a> {
         });
 
         while !q.is_empty() {
-            let mut map = self.modules.borrow_mut();
             let signal = q.pop_front().unwrap();
+            if let Some(trace) = &mut self.trace {
+                trace.record(TraceEvent {
+                    press: self.presses,
+                    seq,
+                    src: signal.src,
+                    dst: signal.dst,
+                    pulse: signal.pulse,
+                });
+            }
+            seq += 1;
+
             match signal.pulse {
                 Pulse::High => self.count_high += 1,
                 Pulse::Low => self.count_low += 1,
@@ -210,15 +646,453 @@ impl<'a> ModuleMap<'a> {
                 self.count_rx += 1;
             }
 
-            if !map.contains_key(signal.dst) {
-                map.insert(signal.dst.to_string(), Module::Untyped { name: signal.dst });
+            if matches!(signal.pulse, Pulse::High) && Some(signal.dst) == self.watched {
+                self.high_into_watched.push(signal.src);
+            }
+
+            let module = self
+                .modules
+                .entry(signal.dst)
+                .or_insert_with(|| Box::new(Sink { name: signal.dst }));
+
+            if let Some(pulse) = module.receive(signal.src, signal.pulse) {
+                for &dst in module.outputs() {
+                    q.push_back(PulseSignal {
+                        src: module.name(),
+                        dst,
+                        pulse,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Starts recording every pulse into `trace`, replacing any trace already attached.
+    fn start_trace(&mut self, trace: Trace<'a>) {
+        self.trace = Some(trace);
+    }
+
+    /// Detaches the trace, flushing it if it goes to a file.
+    fn stop_trace(&mut self) -> Result<Option<Trace<'a>>> {
+        self.trace.take().map(Trace::finish).transpose()
+    }
+
+    /// Presses the button `presses` times, recording every pulse in memory.
+    fn record(&mut self, presses: u64) -> Result<Trace<'a>> {
+        self.start_trace(Trace::in_memory());
+        for _ in 0..presses {
+            self.push_button();
+        }
+        Ok(self.stop_trace()?.unwrap())
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        let states = self
+            .modules
+            .iter()
+            .filter_map(|(name, module)| Some((name.to_string(), module.state()?)))
+            .collect();
+
+        Snapshot {
+            states,
+            presses: self.presses,
+            count_high: self.count_high,
+            count_low: self.count_low,
+        }
+    }
+
+    /// Puts the network back in the state of `snapshot`, which must come from the same input.
+    fn restore(&mut self, snapshot: &Snapshot) -> Result<()> {
+        for (name, module) in self.modules.iter_mut() {
+            if module.state().is_some() {
+                let state = snapshot
+                    .states
+                    .get(*name)
+                    .ok_or_else(|| anyhow!("the snapshot has no state for {name}"))?;
+                module.set_state(state)?;
+            }
+        }
+
+        self.presses = snapshot.presses;
+        self.count_high = snapshot.count_high;
+        self.count_low = snapshot.count_low;
+        Ok(())
+    }
+
+    /// Graphviz rendering of the network, with one shape per module type and `rx` highlighted.
+    /// With `with_state`, flip-flops are coloured by their state and conjunctions list the
+    /// last pulse remembered from each input.
+    fn to_dot(&self, with_state: bool) -> String {
+        let modules = &self.modules;
+        let mut out = String::from("digraph day20 {\n  rankdir=LR;\n");
+
+        // Destinations without a line of their own, like rx, are sinks.
+        let sinks = self
+            .order
+            .iter()
+            .flat_map(|name| modules[*name].outputs())
+            .filter(|name| !self.order.contains(name))
+            .unique()
+            .copied()
+            .collect_vec();
+
+        for &name in self.order.iter().chain(&sinks) {
+            let module = modules.get(name);
+            let shape = module.map_or("ellipse", |m| m.dot_shape());
+            let mut label = format!("{}{name}", module.map_or("", |m| m.prefix()));
+            let mut attrs = vec![format!("shape={shape}")];
+
+            if let (true, Some(module)) = (with_state, module) {
+                module.dot_state(&mut label, &mut attrs);
+            }
+
+            if name == "rx" {
+                attrs.push("style=filled".to_string());
+                attrs.push("fillcolor=tomato".to_string());
+                attrs.push("penwidth=2".to_string());
             }
 
-            if let Some(signals) = map
-                .get_mut(signal.dst)
-                .and_then(|m| m.receive(signal.src, signal.pulse))
-            {
-                signals.into_iter().for_each(|s| q.push_back(s));
+            writeln!(
+                out,
+                "  \"{name}\" [label=\"{label}\", {}];",
+                attrs.join(", ")
+            )
+            .unwrap();
+        }
+
+        for &name in &self.order {
+            for dst in modules[name].outputs() {
+                writeln!(out, "  \"{name}\" -> \"{dst}\";").unwrap();
+            }
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    /// Modules that send pulses to `name`.
+    fn inputs_of(&self, name: &str) -> Vec<&'a str> {
+        self.modules
+            .iter()
+            .filter(|(_, module)| module.outputs().contains(&name))
+            .map(|(_, module)| module.name())
+            .sorted()
+            .collect()
+    }
+
+    /// Every module upstream of `name`, not including the broadcaster.
+    fn subcircuit(&self, name: &'a str) -> Vec<&'a str> {
+        let mut seen = HashSet::from([name]);
+        let mut q = VecDeque::from([name]);
+        while let Some(head) = q.pop_front() {
+            for input in self.inputs_of(head) {
+                if input != "broadcaster" && seen.insert(input) {
+                    q.push_back(input);
+                }
+            }
+        }
+        seen.into_iter().sorted().collect()
+    }
+
+    /// Finds when `rx` gets a low pulse without pressing the button that many times.
+    ///
+    /// `rx` is fed by a single conjunction, which sends it a low pulse only when all of its
+    /// inputs have last sent it a high one. Each input is driven by an independent sub-circuit
+    /// that sends a short high pulse on a fixed period, so the answer is the first press at
+    /// which all periods line up, found with the CRT (the LCM when the cycles start at 0).
+    fn analyze_rx(&mut self, max_presses: u64) -> Result<CycleAnalysis<'a>> {
+        let feeders = self.inputs_of("rx");
+        let [feeder] = feeders[..] else {
+            bail!("rx should have exactly one input, it has {feeders:?}");
+        };
+        if !matches!(self.modules[feeder].kind(), ModuleKind::Conjunction { .. }) {
+            bail!("{feeder}, the input of rx, is not a conjunction");
+        }
+
+        let inputs = self.inputs_of(feeder);
+        let subcircuits = inputs
+            .iter()
+            .map(|&input| self.subcircuit(input))
+            .collect_vec();
+        for ((a, sa), (b, sb)) in inputs.iter().zip(&subcircuits).tuple_combinations() {
+            if let Some(shared) = sa.iter().find(|m| sb.contains(m)) {
+                bail!("the sub-circuits of {a} and {b} both contain {shared}");
+            }
+        }
+
+        self.watched = Some(feeder);
+        let mut seen = inputs.iter().map(|_| Vec::new()).collect_vec();
+        for press in 1..=max_presses {
+            self.push_button();
+            for src in &self.high_into_watched {
+                let idx = inputs.iter().position(|input| input == src).unwrap();
+                // An input can fire more than once in a press, that's still one cycle.
+                if seen[idx].last() != Some(&press) {
+                    seen[idx].push(press);
+                }
+            }
+            if seen.iter().all(|presses| presses.len() >= 2) {
+                break;
+            }
+        }
+        self.watched = None;
+
+        let mut cycles = Vec::new();
+        for ((input, subcircuit), presses) in inputs.into_iter().zip(subcircuits).zip(seen) {
+            let &[first, second, ..] = &presses[..] else {
+                bail!(
+                    "{input} sent a high pulse {} time(s) in {max_presses} presses",
+                    presses.len()
+                );
+            };
+            if second == first {
+                bail!("{input} has a period of 0 presses");
+            }
+            cycles.push(InputCycle {
+                input,
+                subcircuit,
+                first,
+                period: second - first,
+            });
+        }
+
+        let (presses, lcm) = cycles
+            .iter()
+            .map(|c| (c.first as i128 % c.period as i128, c.period as i128))
+            .try_fold((0, 1), crt)
+            .ok_or_else(|| anyhow!("the input cycles never line up"))?;
+
+        // The cycles only hold from their first occurrence onwards.
+        let start = cycles.iter().map(|c| c.first).max().unwrap_or(1) as i128;
+        let behind = (start - presses).max(0);
+        let presses = presses + (behind + lcm - 1) / lcm * lcm;
+
+        Ok(CycleAnalysis {
+            feeder,
+            cycles,
+            presses: presses as u64,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Broadcaster,
+    FlipFlop,
+    Conjunction,
+    Sink,
+}
+
+fn get_bit(bits: &[u64], i: usize) -> bool {
+    bits[i / 64] >> (i % 64) & 1 == 1
+}
+
+fn set_bit(bits: &mut [u64], i: usize, value: bool) {
+    if value {
+        bits[i / 64] |= 1 << (i % 64);
+    } else {
+        bits[i / 64] &= !(1 << (i % 64));
+    }
+}
+
+/// A [`ModuleMap`] compiled down to indices and bits, so that pressing the button doesn't
+/// allocate or hash anything.
+///
+/// Pulses travel along edges: the outputs of module `i` are the edges
+/// `offsets[i]..offsets[i + 1]`, plus one last edge from the button to the broadcaster. The
+/// queue holds `edge << 1 | high` in a ring buffer that only grows if a press ever needs more
+/// room than the previous ones.
+#[derive(Debug)]
+struct Circuit {
+    kinds: Vec<Kind>,
+    offsets: Vec<u32>,
+    targets: Vec<u32>,
+    /// For every edge into a conjunction, the bit of `memory` remembering its last pulse.
+    /// Edges from the same source share their bit.
+    slots: Vec<u32>,
+    /// Flip-flop states, by module.
+    on: Vec<u64>,
+    memory: Vec<u64>,
+    /// How many inputs of each conjunction last sent a high pulse, and how many it has.
+    highs: Vec<u32>,
+    inputs: Vec<u32>,
+    button: u32,
+    rx: Option<u32>,
+    queue: Vec<u32>,
+    head: usize,
+    len: usize,
+    count_high: u64,
+    count_low: u64,
+    count_rx: u64,
+}
+
+impl Circuit {
+    /// Compiles the network in its current state. Only the kinds of the puzzle are supported.
+    fn compile(mm: &ModuleMap) -> Result<Self> {
+        let modules = &mm.modules;
+
+        let mut names = mm.order.clone();
+        let mut sinks = mm
+            .order
+            .iter()
+            .flat_map(|name| modules[*name].outputs())
+            .filter(|name| !mm.order.contains(name))
+            .unique()
+            .copied()
+            .collect_vec();
+        names.append(&mut sinks);
+        let index = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (*name, i as u32))
+            .collect::<HashMap<_, _>>();
+
+        let mut kinds = Vec::with_capacity(names.len());
+        let mut offsets = vec![0];
+        let mut targets = Vec::new();
+        for name in &names {
+            let kind = match modules.get(*name).map(|m| m.kind()) {
+                Some(ModuleKind::Broadcaster) => Kind::Broadcaster,
+                Some(ModuleKind::FlipFlop { .. }) => Kind::FlipFlop,
+                Some(ModuleKind::Conjunction { .. }) => Kind::Conjunction,
+                Some(ModuleKind::Sink) | None => Kind::Sink,
+                Some(kind @ ModuleKind::Other(_)) => {
+                    bail!("module `{name}` has unsupported kind `{kind}`")
+                }
+            };
+            kinds.push(kind);
+
+            if let Some(module) = modules.get(*name) {
+                targets.extend(module.outputs().iter().map(|dst| index[dst]));
+            }
+            offsets.push(targets.len() as u32);
+        }
+
+        let Some(&broadcaster) = index.get("broadcaster") else {
+            bail!("there is no broadcaster");
+        };
+        let button = targets.len() as u32;
+        targets.push(broadcaster);
+
+        let mut on = vec![0; names.len().div_ceil(64)];
+        let mut slots = vec![u32::MAX; targets.len()];
+        let mut slot_of = HashMap::new();
+        let mut inputs = vec![0; names.len()];
+        for (src, name) in names.iter().enumerate() {
+            for edge in offsets[src]..offsets[src + 1] {
+                let dst = targets[edge as usize];
+                if kinds[dst as usize] == Kind::Conjunction {
+                    let next = slot_of.len() as u32;
+                    slots[edge as usize] = *slot_of.entry((src, dst)).or_insert_with(|| {
+                        inputs[dst as usize] += 1;
+                        next
+                    });
+                }
+            }
+
+            if let Some(ModuleKind::FlipFlop { on: state }) = modules.get(*name).map(|m| m.kind()) {
+                set_bit(&mut on, src, state);
+            }
+        }
+
+        // Carry the memories over, for networks compiled mid-simulation.
+        let mut memory = vec![0; slot_of.len().div_ceil(64)];
+        let mut highs = vec![0; names.len()];
+        for (&(src, dst), &slot) in &slot_of {
+            let ModuleKind::Conjunction { memory: remembered } =
+                modules[names[dst as usize]].kind()
+            else {
+                unreachable!("only conjunctions have memory slots");
+            };
+            if remembered.get(names[src]) == Some(&Pulse::High) {
+                set_bit(&mut memory, slot as usize, true);
+                highs[dst as usize] += 1;
+            }
+        }
+
+        let queue = vec![0; (targets.len() + 1).next_power_of_two()];
+        Ok(Self {
+            kinds,
+            offsets,
+            targets,
+            slots,
+            on,
+            memory,
+            highs,
+            inputs,
+            button,
+            rx: index.get("rx").copied(),
+            queue,
+            head: 0,
+            len: 0,
+            count_high: 0,
+            count_low: 0,
+            count_rx: 0,
+        })
+    }
+
+    fn push(&mut self, edge: u32, high: bool) {
+        if self.len == self.queue.len() {
+            self.queue.rotate_left(self.head);
+            self.head = 0;
+            self.queue.resize(self.len * 2, 0);
+        }
+        let mask = self.queue.len() - 1;
+        self.queue[(self.head + self.len) & mask] = edge << 1 | high as u32;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<(usize, bool)> {
+        if self.len == 0 {
+            return None;
+        }
+        let entry = self.queue[self.head];
+        self.head = (self.head + 1) & (self.queue.len() - 1);
+        self.len -= 1;
+        Some(((entry >> 1) as usize, entry & 1 == 1))
+    }
+
+    fn push_button(&mut self) {
+        self.count_rx = 0;
+        self.push(self.button, false);
+
+        while let Some((edge, high)) = self.pop() {
+            if high {
+                self.count_high += 1;
+            } else {
+                self.count_low += 1;
+            }
+
+            let dst = self.targets[edge] as usize;
+            if !high && self.rx == Some(dst as u32) {
+                self.count_rx += 1;
+            }
+
+            let pulse = match self.kinds[dst] {
+                Kind::Broadcaster => high,
+                Kind::FlipFlop if high => continue,
+                Kind::FlipFlop => {
+                    let on = !get_bit(&self.on, dst);
+                    set_bit(&mut self.on, dst, on);
+                    on
+                }
+                Kind::Conjunction => {
+                    let slot = self.slots[edge] as usize;
+                    if get_bit(&self.memory, slot) != high {
+                        set_bit(&mut self.memory, slot, high);
+                        if high {
+                            self.highs[dst] += 1;
+                        } else {
+                            self.highs[dst] -= 1;
+                        }
+                    }
+                    self.highs[dst] != self.inputs[dst]
+                }
+                Kind::Sink => continue,
+            };
+
+            for out in self.offsets[dst]..self.offsets[dst + 1] {
+                self.push(out, pulse);
             }
         }
     }
@@ -230,30 +1104,103 @@ impl<'a> ModuleMap<'a> {
 
 impl AocSolution for Solution {
     const DAY: u8 = 20;
+    const EXPORTS: &'static [(&'static str, &'static str)] = &[
+        (
+            "dot",
+            "Graphviz module network, with the module states after --steps presses or --from a snapshot",
+        ),
+        (
+            "trace",
+            "every pulse of --steps presses (1 by default) as JSON lines, --from a snapshot if given",
+        ),
+        (
+            "pulses",
+            "pulses sent by each module in --steps presses (1000 by default) or a --from trace",
+        ),
+        (
+            "press",
+            "pulses of press number --steps (1 by default), simulated or from a --from trace",
+        ),
+        (
+            "snapshot",
+            "module states after --steps presses (1000 by default), --from a snapshot if given",
+        ),
+    ];
 
     fn new() -> Self {
         Self
     }
 
     fn part1(&self, input: &str) -> u64 {
-        let mut mm = ModuleMap::parse(input);
+        let mut circuit = Circuit::compile(&ModuleMap::parse(input)).unwrap();
         for _ in 0..1000 {
-            mm.push_button();
+            circuit.push_button();
         }
-        mm.counts()
+        circuit.counts()
     }
 
     fn part2(&self, input: &str) -> u64 {
         let mut mm = ModuleMap::parse(input);
-        (0u64..)
-            .find(|i| {
-                mm.push_button();
-                if i % 10000 == 0 {
-                    println!("{i} {}", mm.count_rx);
+        mm.analyze_rx(100_000).unwrap().presses
+    }
+
+    fn export(&self, input: &str, name: &str, export: &Export) -> Result<()> {
+        let mut mm = ModuleMap::parse_with(input, &Registry::standard())?;
+
+        // The views of the module states carry on from a `snapshot`, the others read a `trace`.
+        let from = export.read_from()?;
+        if let ("dot" | "trace" | "snapshot", Some(json)) = (name, &from) {
+            let snapshot = serde_json::from_str(json).context("--from isn't a snapshot")?;
+            mm.restore(&snapshot)?;
+        }
+
+        match name {
+            "dot" => {
+                for _ in 0..export.steps_or(0) {
+                    mm.push_button();
                 }
-                mm.count_rx == 1
-            })
-            .unwrap()
+                let file = match export.steps {
+                    Some(presses) => format!("day20-{presses}.dot"),
+                    None => "day20.dot".to_string(),
+                };
+                let with_state = export.steps.is_some() || from.is_some();
+                export.write(&file, &mm.to_dot(with_state))
+            }
+            "trace" => {
+                mm.start_trace(Trace::to_file(export.create("day20-trace.jsonl")?));
+                for _ in 0..export.steps_or(1) {
+                    mm.push_button();
+                }
+                mm.stop_trace()?;
+                Ok(())
+            }
+            "pulses" => {
+                let trace = match &from {
+                    Some(jsonl) => Trace::parse_jsonl(jsonl)?,
+                    None => mm.record(export.steps_or(1000))?,
+                };
+                export.write("day20-pulses.txt", &render_pulses(&trace)?)
+            }
+            "press" => {
+                let press = export.steps_or(1);
+                let trace = match &from {
+                    Some(jsonl) => Trace::parse_jsonl(jsonl)?,
+                    None => mm.record(press)?,
+                };
+                export.write(
+                    &format!("day20-press{press}.txt"),
+                    &render_press(&trace, press)?,
+                )
+            }
+            "snapshot" => {
+                for _ in 0..export.steps_or(1000) {
+                    mm.push_button();
+                }
+                let json = serde_json::to_string_pretty(&mm.snapshot())?;
+                export.write("day20-snapshot.json", &(json + "\n"))
+            }
+            _ => bail!("unknown export {name}"),
+        }
     }
 }
 
@@ -281,19 +1228,391 @@ mod tests {
     "
     );
 
+    // Two counters that reset themselves every 3 and 5 presses, each sending a short high
+    // pulse through an inverter into the conjunction feeding rx, like the real inputs do.
+    const TEST_CASE_RX: &str = textwrap_macros::dedent!(
+        r"
+    broadcaster -> a0, b0
+    %a0 -> a1, ca
+    %a1 -> ca
+    &ca -> a0, ia
+    &ia -> df
+    %b0 -> b1, cb
+    %b1 -> b2
+    %b2 -> cb
+    &cb -> b0, b1, ib
+    &ib -> df
+    &df -> rx
+    "
+    );
+
+    /// A kind that isn't part of the puzzle: sends a high pulse if any input last sent one.
+    #[derive(Debug)]
+    struct OrGate<'a> {
+        name: &'a str,
+        outputs: Vec<&'a str>,
+        memory: HashMap<&'a str, Pulse>,
+    }
+
+    impl<'a> PulseModule<'a> for OrGate<'a> {
+        fn name(&self) -> &'a str {
+            self.name
+        }
+
+        fn outputs(&self) -> &[&'a str] {
+            &self.outputs
+        }
+
+        fn kind(&self) -> ModuleKind<'_, 'a> {
+            ModuleKind::Other("or")
+        }
+
+        fn prefix(&self) -> &'static str {
+            "|"
+        }
+
+        fn connect(&mut self, src: &'a str) {
+            self.memory.insert(src, Pulse::Low);
+        }
+
+        fn receive(&mut self, src: &'a str, pulse: Pulse) -> Option<Pulse> {
+            self.memory.insert(src, pulse);
+            if self.memory.values().any(|&p| p == Pulse::High) {
+                Some(Pulse::High)
+            } else {
+                Some(Pulse::Low)
+            }
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let input = "broadcaster -> a\n%a -> b, or\n%b -> or\n|or -> out";
+        assert!(ModuleMap::parse_with(input, &Registry::standard()).is_err());
+        assert!(ModuleMap::parse_with("broadcaster", &Registry::standard()).is_err());
+
+        let mut registry = Registry::standard();
+        registry.register("|", |name, outputs| {
+            Box::new(OrGate {
+                name,
+                outputs,
+                memory: HashMap::new(),
+            })
+        });
+        let mut mm = ModuleMap::parse_with(input, &registry).unwrap();
+        assert!(mm
+            .to_dot(false)
+            .contains("\"or\" [label=\"|or\", shape=ellipse];"));
+
+        // Custom kinds are never mistaken for the puzzle's own.
+        let err = Circuit::compile(&mm).unwrap_err();
+        assert_eq!(err.to_string(), "module `or` has unsupported kind `or`");
+        let or_feeds_rx = "broadcaster -> a\n%a -> or\n|or -> rx";
+        let err = ModuleMap::parse_with(or_feeds_rx, &registry)
+            .unwrap()
+            .analyze_rx(10)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "or, the input of rx, is not a conjunction");
+
+        mm.start_trace(Trace::in_memory());
+        mm.push_button();
+        mm.push_button();
+        let trace = mm.stop_trace().unwrap().unwrap();
+
+        let sent_by_or = |press| {
+            trace
+                .press(press)
+                .unwrap()
+                .filter(|e| e.src == "or")
+                .map(|e| e.pulse)
+                .collect_vec()
+        };
+        assert_eq!(sent_by_or(1), [Pulse::High]);
+        assert_eq!(sent_by_or(2), [Pulse::Low, Pulse::High]);
+    }
+
+    #[test]
+    fn test_crt() {
+        assert_eq!(crt((0, 1), (0, 3)), Some((0, 3)));
+        assert_eq!(crt((2, 3), (3, 5)), Some((8, 15)));
+        assert_eq!(crt((1, 4), (3, 6)), Some((9, 12)));
+        assert_eq!(crt((0, 4), (1, 6)), None);
+    }
+
+    #[test]
+    fn test_analyze_rx() {
+        let mut mm = ModuleMap::parse(TEST_CASE_RX);
+        let analysis = mm.analyze_rx(1000).unwrap();
+
+        assert_eq!(analysis.feeder, "df");
+        assert_eq!(
+            analysis
+                .cycles
+                .iter()
+                .map(|c| (c.input, c.first, c.period))
+                .collect_vec(),
+            [("ia", 3, 3), ("ib", 5, 5)]
+        );
+        assert_eq!(analysis.cycles[0].subcircuit, ["a0", "a1", "ca", "ia"]);
+        assert_eq!(analysis.presses, 15);
+
+        // Pressing the button that many times really does send a single low pulse to rx.
+        let mut mm = ModuleMap::parse(TEST_CASE_RX);
+        let brute_force = (1u64..)
+            .find(|_| {
+                mm.push_button();
+                mm.count_rx == 1
+            })
+            .unwrap();
+        assert_eq!(brute_force, analysis.presses);
+    }
+
+    #[test]
+    fn test_analyze_rx_errors() {
+        assert!(ModuleMap::parse(TEST_CASE).analyze_rx(1000).is_err());
+
+        let inverter_feeds_rx = "broadcaster -> a\n%a -> rx";
+        assert!(ModuleMap::parse(inverter_feeds_rx)
+            .analyze_rx(1000)
+            .is_err());
+
+        // ib never fires within the limit.
+        let mut mm = ModuleMap::parse(TEST_CASE_RX);
+        assert!(mm.analyze_rx(6).is_err());
+
+        // a sends two high pulses to df on every press, which used to make its period 0 and
+        // the CRT divide by zero. It fires on every press, as does b.
+        let twice = "broadcaster -> a, a, b\n&a -> df\n&b -> df\n&df -> rx";
+        let analysis = ModuleMap::parse(twice).analyze_rx(1000).unwrap();
+        assert_eq!(
+            analysis
+                .cycles
+                .iter()
+                .map(|c| (c.input, c.first, c.period))
+                .collect_vec(),
+            [("a", 1, 1), ("b", 1, 1)]
+        );
+        assert_eq!(analysis.presses, 1);
+        let mut mm = ModuleMap::parse(twice);
+        mm.push_button();
+        assert_eq!(mm.count_rx, 1);
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let mut mm = ModuleMap::parse(TEST_CASE2);
+        let dot = mm.to_dot(false);
+
+        let lines = dot.lines().collect_vec();
+        assert_eq!(
+            lines[2],
+            "  \"broadcaster\" [label=\"broadcaster\", shape=house];"
+        );
+        assert_eq!(lines[3], "  \"a\" [label=\"%a\", shape=box];");
+        assert_eq!(lines[4], "  \"inv\" [label=\"&inv\", shape=invtrapezium];");
+        assert_eq!(lines[7], "  \"output\" [label=\"output\", shape=ellipse];");
+
+        // Edges follow the input, line by line and output by output.
+        let edges = lines
+            .iter()
+            .filter(|l| l.contains(" -> "))
+            .copied()
+            .collect_vec();
+        assert_eq!(
+            edges,
+            [
+                "  \"broadcaster\" -> \"a\";",
+                "  \"a\" -> \"inv\";",
+                "  \"a\" -> \"con\";",
+                "  \"inv\" -> \"b\";",
+                "  \"b\" -> \"con\";",
+                "  \"con\" -> \"output\";",
+            ]
+        );
+
+        mm.push_button();
+        let dot = mm.to_dot(true);
+        assert!(dot.contains("\"a\" [label=\"%a\", shape=box, style=filled, fillcolor=palegreen];"));
+        assert!(dot.contains("label=\"&con\\na=H\\nb=H\""));
+
+        let dot = ModuleMap::parse(TEST_CASE_RX).to_dot(false);
+        assert!(dot.contains("\"rx\" [label=\"rx\", shape=ellipse, style=filled, fillcolor=tomato"));
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut mm = ModuleMap::parse(TEST_CASE2);
+        mm.start_trace(Trace::in_memory());
+        for _ in 0..4 {
+            mm.push_button();
+        }
+        let trace = mm.stop_trace().unwrap().unwrap();
+
+        let first = trace
+            .press(1)
+            .unwrap()
+            .map(|e| (e.seq, e.src, e.dst, e.pulse))
+            .collect_vec();
+        assert_eq!(
+            first,
+            [
+                (0, "button", "broadcaster", Pulse::Low),
+                (1, "broadcaster", "a", Pulse::Low),
+                (2, "a", "inv", Pulse::High),
+                (3, "a", "con", Pulse::High),
+                (4, "inv", "b", Pulse::Low),
+                (5, "con", "output", Pulse::High),
+                (6, "b", "con", Pulse::High),
+                (7, "con", "output", Pulse::Low),
+            ]
+        );
+        let first_press = |module, pulse| trace.first_press_sending(module, pulse).unwrap();
+        assert_eq!(first_press("b", Pulse::High), Some(1));
+        assert_eq!(first_press("b", Pulse::Low), Some(3));
+        assert_eq!(first_press("output", Pulse::Low), None);
+
+        // The example's totals for 4 presses are 17 low and 11 high pulses.
+        let per_module = trace.pulses_per_module().unwrap();
+        assert_eq!(per_module["button"], PulseCounts { high: 0, low: 4 });
+        assert_eq!(per_module.values().map(|c| c.low).sum::<u64>(), 17);
+        assert_eq!(per_module.values().map(|c| c.high).sum::<u64>(), 11);
+        assert_eq!((mm.count_low, mm.count_high), (17, 11));
+
+        let table = render_pulses(&trace).unwrap();
+        assert!(table
+            .lines()
+            .any(|line| line.split('|').map(str::trim).eq(["b", "1", "1", "1", "3"])));
+
+        let press = render_press(&trace, 1).unwrap();
+        assert_eq!(press.lines().next(), Some("button -low-> broadcaster"));
+        assert_eq!(press.lines().last(), Some("con -low-> output"));
+    }
+
+    #[test]
+    fn test_trace_file() {
+        let path = std::env::temp_dir().join(format!("day20-trace-{}.jsonl", std::process::id()));
+
+        let mut mm = ModuleMap::parse(TEST_CASE);
+        let file = std::fs::File::create(&path).unwrap();
+        mm.start_trace(Trace::to_file(ExportFile::File(file)));
+        mm.push_button();
+        mm.push_button();
+        let written = mm.stop_trace().unwrap().unwrap();
+        assert!(written.events().is_err());
+        assert!(render_pulses(&written).is_err());
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            text.lines().next().unwrap(),
+            r#"{"press":1,"seq":0,"src":"button","dst":"broadcaster","pulse":"low"}"#
+        );
+
+        let in_memory = ModuleMap::parse(TEST_CASE).record(2).unwrap();
+        let replayed = Trace::parse_jsonl(&text).unwrap();
+        assert_eq!(replayed.events().unwrap(), in_memory.events().unwrap());
+        assert!(Trace::parse_jsonl("{\"press\":1}").is_err());
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut mm = ModuleMap::parse(TEST_CASE_RX);
+        for _ in 0..2 {
+            mm.push_button();
+        }
+        let snapshot = mm.snapshot();
+
+        mm.start_trace(Trace::in_memory());
+        for _ in 0..5 {
+            mm.push_button();
+        }
+        let first_run = mm.stop_trace().unwrap().unwrap();
+        assert_ne!(mm.snapshot(), snapshot);
+
+        mm.restore(&snapshot).unwrap();
+        assert_eq!(mm.snapshot(), snapshot);
+
+        mm.start_trace(Trace::in_memory());
+        for _ in 0..5 {
+            mm.push_button();
+        }
+        let second_run = mm.stop_trace().unwrap().unwrap();
+        assert_eq!(first_run.events().unwrap(), second_run.events().unwrap());
+        assert_eq!(
+            second_run.first_press_sending("ib", Pulse::High).unwrap(),
+            Some(5)
+        );
+
+        // Snapshots go through the `snapshot` export and come back with `--from`.
+        let json = serde_json::to_string_pretty(&snapshot).unwrap();
+        assert_eq!(serde_json::from_str::<Snapshot>(&json).unwrap(), snapshot);
+
+        let other = ModuleMap::parse(TEST_CASE).snapshot();
+        assert!(mm.restore(&other).is_err());
+    }
+
+    #[test]
+    fn test_circuit() {
+        for (input, part1) in [(TEST_CASE, 32000000), (TEST_CASE2, 11687500)] {
+            assert_eq!(Solution.part1(input), part1);
+        }
+
+        for input in [TEST_CASE, TEST_CASE2, TEST_CASE_RX] {
+            let mut mm = ModuleMap::parse(input);
+            let mut circuit = Circuit::compile(&mm).unwrap();
+            for _ in 0..1000 {
+                mm.push_button();
+                circuit.push_button();
+                assert_eq!(
+                    (circuit.count_low, circuit.count_high, circuit.count_rx),
+                    (mm.count_low, mm.count_high, mm.count_rx)
+                );
+            }
+        }
+
+        // Compiling mid-simulation carries the states over.
+        let mut mm = ModuleMap::parse(TEST_CASE_RX);
+        mm.push_button();
+        mm.push_button();
+        let mut circuit = Circuit::compile(&mm).unwrap();
+        let (low, high) = (mm.count_low, mm.count_high);
+        for _ in 0..100 {
+            mm.push_button();
+            circuit.push_button();
+        }
+        assert_eq!(
+            (circuit.count_low, circuit.count_high),
+            (mm.count_low - low, mm.count_high - high)
+        );
+    }
+
     #[test]
     fn test_parse() {
         let mut mm = ModuleMap::parse(TEST_CASE);
         for _ in 0..1000 {
             mm.push_button();
         }
-        println!("{}x{} -> {}", mm.count_low, mm.count_high, mm.counts());
+        println!("{}x{}", mm.count_low, mm.count_high);
 
         let mut mm = ModuleMap::parse(TEST_CASE2);
         for _ in 0..1000 {
             mm.push_button();
         }
-        println!("{}x{} -> {}", mm.count_low, mm.count_high, mm.counts());
+        println!("{}x{}", mm.count_low, mm.count_high);
+    }
+
+    #[test]
+    fn test_counts() {
+        for (input, low, high) in [(TEST_CASE, 8000, 4000), (TEST_CASE2, 4250, 2750)] {
+            let mut mm = ModuleMap::parse(input);
+            let mut circuit = Circuit::compile(&mm).unwrap();
+            for _ in 0..1000 {
+                mm.push_button();
+                circuit.push_button();
+            }
+            assert_eq!((mm.count_low, mm.count_high), (low, high));
+            assert_eq!((circuit.count_low, circuit.count_high), (low, high));
+        }
     }
 }
 
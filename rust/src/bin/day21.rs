@@ -1,11 +1,13 @@
-use adventofcode2023::AocSolution;
+use adventofcode2023::{export::Export, format_table, AocSolution};
 
 use std::{
-    collections::BTreeSet,
-    ops::{Div, Rem},
+    collections::{BTreeSet, VecDeque},
+    fmt,
 };
 
+use anyhow::{bail, Result};
 use hashbrown::HashMap;
+use itertools::Itertools;
 
 pub struct Solution;
 
@@ -28,44 +30,17 @@ impl From<Direction> for (isize, isize) {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-enum Wrap {
-    Inside(Point),
-    North(Point),
-    South(Point),
-    West(Point),
-    East(Point),
-}
-
-impl Wrap {
-    fn inside(self) -> Option<Point> {
-        match self {
-            Wrap::Inside(p) => Some(p),
-            _ => None,
-        }
-    }
-}
-
 #[derive(Default, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Debug)]
 struct Point(isize, isize);
 
 impl Point {
-    fn add(self, direction: Direction, width: isize, height: isize) -> Wrap {
+    /// The neighbour in `direction`, if it is still inside a `width` x `height` tile.
+    fn add(self, direction: Direction, width: isize, height: isize) -> Option<Point> {
         let (x, y) = direction.into();
         let x = self.0 + x;
         let y = self.1 + y;
 
-        if (0..width).contains(&x) && (0..height).contains(&y) {
-            Wrap::Inside(Point(x, y))
-        } else {
-            let p = Point(x % width, y % height);
-            match direction {
-                Direction::North => Wrap::North(p),
-                Direction::South => Wrap::South(p),
-                Direction::West => Wrap::West(p),
-                Direction::East => Wrap::East(p),
-            }
-        }
+        ((0..width).contains(&x) && (0..height).contains(&y)).then_some(Point(x, y))
     }
 }
 
@@ -79,6 +54,7 @@ struct Field {
 impl Field {
     fn parse(input: &str) -> (Field, Point) {
         input
+            .trim()
             .lines()
             .enumerate()
             .flat_map(move |(y, line)| {
@@ -99,79 +75,367 @@ impl Field {
                 },
             )
     }
+
+    fn is_clear_row(&self, y: isize) -> bool {
+        (0..self.width).all(|x| self.points.contains(&Point(x, y)))
+    }
+
+    fn is_clear_column(&self, x: isize) -> bool {
+        (0..self.height).all(|y| self.points.contains(&Point(x, y)))
+    }
+
+    fn distance_map(&self, start: Point) -> DistanceMap {
+        self.distance_map_from(&[start])
+    }
+
+    /// Shortest distances within the tile from any of `starts`, all of them setting off at
+    /// the same time.
+    ///
+    /// Walking back and forth keeps the parity of a distance, so the BFS keeps the shortest
+    /// even and the shortest odd distance of every plot. They only both exist when the starts
+    /// don't all have the same colour on the checkerboard.
+    fn distance_map_from(&self, starts: &[Point]) -> DistanceMap {
+        let mut distances = HashMap::<Point, [Option<usize>; 2]>::new();
+        let mut q = VecDeque::new();
+        for &start in starts {
+            if self.points.contains(&start) {
+                distances.entry(start).or_default()[0] = Some(0);
+                q.push_back((start, 0));
+            }
+        }
+
+        while let Some((p, d)) = q.pop_front() {
+            for direction in [
+                Direction::North,
+                Direction::South,
+                Direction::West,
+                Direction::East,
+            ] {
+                if let Some(next) = p.add(direction, self.width, self.height) {
+                    if !self.points.contains(&next) {
+                        continue;
+                    }
+                    let slot = &mut distances.entry(next).or_default()[(d + 1) % 2];
+                    if slot.is_none() {
+                        *slot = Some(d + 1);
+                        q.push_back((next, d + 1));
+                    }
+                }
+            }
+        }
+
+        DistanceMap::new(distances)
+    }
 }
 
-#[derive(Default, Clone, Debug, PartialEq, Eq, Hash)]
-struct Config(BTreeSet<Point>);
+/// The tile with the last digit of the distance to every plot, `.` for the plots that can't
+/// be reached, then the number of plots reachable in exactly `0..=steps` steps.
+fn render_distances(field: &Field, map: &DistanceMap, steps: u64) -> String {
+    let mut out = String::new();
+    for y in 0..field.height {
+        for x in 0..field.width {
+            let p = Point(x, y);
+            out.push(match map.distance(p) {
+                Some(d) => char::from_digit(d as u32 % 10, 10).unwrap(),
+                None if field.points.contains(&p) => '.',
+                None => '#',
+            });
+        }
+        out.push('\n');
+    }
 
-impl Config {
-    fn neighborhood(&self, field: &Field) -> Self {
-        let (w, h) = (field.width, field.height);
-        Self(
-            self.0
-                .iter()
-                .copied()
-                .flat_map(|p| {
-                    [
-                        p.add(Direction::North, w, h).inside(),
-                        p.add(Direction::South, w, h).inside(),
-                        p.add(Direction::West, w, h).inside(),
-                        p.add(Direction::East, w, h).inside(),
-                    ]
-                    .into_iter()
-                })
-                .flatten()
-                .filter(|p| field.points.contains(p))
-                .collect(),
-        )
+    let steps = (0..=steps).collect_vec();
+    let rows = steps
+        .iter()
+        .zip(map.reachable_all(&steps))
+        .map(|(n, plots)| [n.to_string(), plots.to_string()])
+        .collect_vec();
+    out + "\n" + &format_table(&["Steps", "Plots"], &rows)
+}
+
+/// Shortest distances to the plots of a tile, see [`Field::distance_map_from`].
+struct DistanceMap {
+    distances: HashMap<Point, [Option<usize>; 2]>,
+    /// `cumulative[p][d]` is the number of plots whose shortest distance of parity `p` is at
+    /// most `d`.
+    cumulative: [Vec<u64>; 2],
+}
+
+impl DistanceMap {
+    fn new(distances: HashMap<Point, [Option<usize>; 2]>) -> Self {
+        let max = distances
+            .values()
+            .flatten()
+            .flatten()
+            .copied()
+            .max()
+            .unwrap_or(0);
+        let mut cumulative = [vec![0; max + 1], vec![0; max + 1]];
+        for &d in distances.values().flatten().flatten() {
+            cumulative[d % 2][d] += 1;
+        }
+        for counts in &mut cumulative {
+            for d in 1..=max {
+                counts[d] += counts[d - 1];
+            }
+        }
+        Self {
+            distances,
+            cumulative,
+        }
+    }
+
+    /// The number of steps needed to reach `p`, if it can be reached at all.
+    fn distance(&self, p: Point) -> Option<usize> {
+        self.distances.get(&p)?.iter().flatten().min().copied()
+    }
+
+    fn max_distance(&self) -> u64 {
+        self.cumulative[0].len() as u64 - 1
     }
 
-    fn count(&self) -> usize {
-        self.0.len()
+    /// The number of plots that can be reached in exactly `steps` steps.
+    fn reachable(&self, steps: u64) -> u64 {
+        self.cumulative[(steps % 2) as usize][steps.min(self.max_distance()) as usize]
+    }
+
+    fn reachable_all(&self, steps: &[u64]) -> Vec<u64> {
+        steps.iter().map(|&n| self.reachable(n)).collect()
+    }
+
+    /// Sum of `reachable(r)` for `r = first, first - step, first - 2 * step, ...` while `r`
+    /// is not negative. Tiles that are entirely within reach are counted without iterating.
+    fn reachable_series(&self, first: i64, step: i64) -> u64 {
+        let max = self.max_distance() as i64;
+        let mut total = 0;
+        let mut r = first;
+
+        if r >= max {
+            let full = (r - max) / step + 1;
+            let (same, other) = if step % 2 == 0 {
+                (full, 0)
+            } else {
+                ((full + 1) / 2, full / 2)
+            };
+            let parity = (r % 2) as usize;
+            total += same as u64 * self.cumulative[parity][max as usize]
+                + other as u64 * self.cumulative[1 - parity][max as usize];
+            r -= full * step;
+        }
+
+        while r >= 0 {
+            total += self.reachable(r as u64);
+            r -= step;
+        }
+
+        total
     }
 }
 
-struct ConfigMap {
-    field: Field,
-    current: Config,
-    map: HashMap<Config, Config>,
+/// Counts the plots reachable in exactly `steps` steps on the infinitely tiled garden.
+///
+/// Relies on the structure of the real inputs: the row and the column of the start and the
+/// border of the tile are free of rocks. A shortest path to another tile then runs straight
+/// along them and enters it through the middle of an edge, for the tiles in line with the
+/// start, or through a corner, for the others. Distances within a tile are then the BFS
+/// distances from that entry, offset by the distance to it, and only their parity matters.
+fn count_tiled(field: &Field, start: Point, steps: u64) -> Result<u64> {
+    let (w, h) = (field.width, field.height);
+    let Point(sx, sy) = start;
+
+    for (what, clear) in [
+        ("row of the start", field.is_clear_row(sy)),
+        ("column of the start", field.is_clear_column(sx)),
+        ("top row", field.is_clear_row(0)),
+        ("bottom row", field.is_clear_row(h - 1)),
+        ("left column", field.is_clear_column(0)),
+        ("right column", field.is_clear_column(w - 1)),
+    ] {
+        if !clear {
+            bail!("the {what} has rocks, the infinite garden can't be solved by tiles");
+        }
+    }
+
+    let steps = steps as i64;
+    let (w, h) = (w as i64, h as i64);
+    let mut total = 0;
+
+    // For each direction: where tiles are entered, and the distance to the nearest one.
+    let along = |d: i64, s: isize, size: i64| match d {
+        0 => (s, 0),
+        1 => (0, size - s as i64),
+        _ => (size as isize - 1, s as i64 + 1),
+    };
+
+    for dx in [-1, 0, 1] {
+        for dy in [-1, 0, 1] {
+            let (x, base_x) = along(dx, sx, w);
+            let (y, base_y) = along(dy, sy, h);
+            let counts = field.distance_map(Point(x, y));
+            let first = steps - base_x - base_y;
+
+            total += match (dx, dy) {
+                (0, 0) => counts.reachable(first as u64),
+                (_, 0) => counts.reachable_series(first, w),
+                (0, _) => counts.reachable_series(first, h),
+                // A column of tiles for every tile of the row.
+                _ => (0..)
+                    .map(|i| first - i * w)
+                    .take_while(|&r| r >= 0)
+                    .map(|r| counts.reachable_series(r, h))
+                    .sum(),
+            };
+        }
+    }
+
+    Ok(total)
 }
 
-impl ConfigMap {
-    fn new(field: Field, start: Point) -> Self {
-        let mut current = Config::default();
-        current.0.insert(start);
+/// Reference simulation of the infinitely tiled garden, which follows every plot across
+/// tile boundaries instead of relying on the structure of the input, see the `simulate`
+/// export.
+struct InfiniteGarden {
+    open: Vec<bool>,
+    width: isize,
+    height: isize,
+}
+
+/// Plots reachable in exactly some number of steps, with how many fall in each tile.
+#[derive(Debug)]
+struct Reachable {
+    total: u64,
+    /// Tile coordinates of `tiles[0][0]`; the start is in tile `(0, 0)`.
+    origin: (isize, isize),
+    tiles: Vec<Vec<u64>>,
+}
+
+impl InfiniteGarden {
+    fn new(field: &Field) -> Self {
+        let open = (0..field.height)
+            .flat_map(|y| (0..field.width).map(move |x| Point(x, y)))
+            .map(|p| field.points.contains(&p))
+            .collect();
         Self {
-            field,
-            current,
-            map: Default::default(),
+            open,
+            width: field.width,
+            height: field.height,
         }
     }
 
-    fn reinit(&mut self, start: Point) {
-        self.current = Config::default();
-        self.current.0.insert(start);
+    /// Whether `p`, anywhere on the infinite garden, is a plot.
+    fn is_open(&self, p: Point) -> bool {
+        let (x, y) = (p.0.rem_euclid(self.width), p.1.rem_euclid(self.height));
+        self.open[(y * self.width + x) as usize]
     }
 
-    fn step(&mut self) -> bool {
-        if let Some(next) = self.map.get(&self.current) {
-            self.current = next.clone();
-            true
-        } else {
-            let next = self.current.neighborhood(&self.field);
-            self.map.insert(self.current.clone(), next.clone());
-            self.current = next;
-            false
+    fn tile(&self, p: Point) -> (isize, isize) {
+        (p.0.div_euclid(self.width), p.1.div_euclid(self.height))
+    }
+
+    /// Walks from `start` one BFS layer at a time. Plots reached at a distance of the same
+    /// parity as `steps` can be reached again by going back and forth, so they are exactly
+    /// the ones reachable after `steps` steps.
+    fn simulate(&self, start: Point, steps: usize) -> Reachable {
+        let radius = steps as isize;
+        let (x0, y0) = self.tile(Point(start.0 - radius, start.1 - radius));
+        let (x1, y1) = self.tile(Point(start.0 + radius, start.1 + radius));
+        let mut tiles = vec![vec![0; (x1 - x0 + 1) as usize]; (y1 - y0 + 1) as usize];
+        let mut total = 0;
+
+        // Everything reachable lies in the square of side 2 * steps + 1 around the start.
+        let side = 2 * radius + 1;
+        let mut visited = vec![0u64; (side * side) as usize / 64 + 1];
+        let index = |p: Point| ((p.1 - start.1 + radius) * side + p.0 - start.0 + radius) as usize;
+        visited[index(start) / 64] |= 1 << (index(start) % 64);
+
+        let mut layer = vec![start];
+        let mut next = Vec::new();
+        for distance in 0..=steps {
+            if distance % 2 == steps % 2 {
+                total += layer.len() as u64;
+                for &p in &layer {
+                    let (tx, ty) = self.tile(p);
+                    tiles[(ty - y0) as usize][(tx - x0) as usize] += 1;
+                }
+            }
+
+            if distance == steps {
+                break;
+            }
+
+            for &Point(x, y) in &layer {
+                for p in [
+                    Point(x, y - 1),
+                    Point(x, y + 1),
+                    Point(x - 1, y),
+                    Point(x + 1, y),
+                ] {
+                    let i = index(p);
+                    if visited[i / 64] >> (i % 64) & 1 == 0 && self.is_open(p) {
+                        visited[i / 64] |= 1 << (i % 64);
+                        next.push(p);
+                    }
+                }
+            }
+            std::mem::swap(&mut layer, &mut next);
+            next.clear();
         }
+
+        Reachable {
+            total,
+            origin: (x0, y0),
+            tiles,
+        }
+    }
+}
+
+impl Reachable {
+    fn tile(&self, tx: isize, ty: isize) -> u64 {
+        let (x, y) = (tx - self.origin.0, ty - self.origin.1);
+        self.tiles
+            .get(y as usize)
+            .and_then(|row| row.get(x as usize))
+            .copied()
+            .unwrap_or(0)
     }
+}
 
-    fn count(&self) -> usize {
-        self.current.0.len()
+impl fmt::Display for Reachable {
+    /// The per-tile counts as a matrix, empty tiles shown as `.`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .tiles
+            .iter()
+            .flatten()
+            .map(|n| n.to_string().len())
+            .max()
+            .unwrap_or(1);
+        for row in &self.tiles {
+            let line = row
+                .iter()
+                .map(|&n| match n {
+                    0 => format!("{:>width$}", "."),
+                    n => format!("{n:>width$}"),
+                })
+                .join(" ");
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
     }
 }
 
 impl AocSolution for Solution {
     const DAY: u8 = 21;
+    const EXPORTS: &'static [(&'static str, &'static str)] = &[
+        (
+            "simulate",
+            "plots reachable in --steps steps (1000 by default) on the infinite garden, per tile",
+        ),
+        (
+            "distances",
+            "distance from the start to each plot of the tile, and the plots reachable in exactly 0 to --steps steps (64 by default)",
+        ),
+    ];
 
     fn new() -> Self {
         Self
@@ -179,54 +443,42 @@ impl AocSolution for Solution {
 
     fn part1(&self, input: &str) -> u64 {
         let (field, start) = Field::parse(input);
-        let mut config_map = ConfigMap::new(field, start);
-
-        for _ in 0..64 {
-            config_map.step();
-        }
-
-        config_map.count() as u64
+        field.distance_map(start).reachable(64)
     }
 
     fn part2(&self, input: &str) -> u64 {
-        const STEPS: usize = 26501365;
+        let (field, start) = Field::parse(input);
+        count_tiled(&field, start, 26501365).unwrap()
+    }
 
+    fn export(&self, input: &str, name: &str, export: &Export) -> Result<()> {
         let (field, start) = Field::parse(input);
-        let mut config_map = ConfigMap::new(field, start);
-
-        // For the input, the states repeat first at i = field.width and then repeat at every
-        // single step. No point in calculating statuses farther.
-        let wraps = config_map.field.width as usize;
-
-        let div = STEPS.div(wraps);
-        let rem = STEPS.rem(wraps);
-        println!("Div {div} Rem {rem} wraps {wraps}");
-
-        // Reinitialize to count the steps in a full cycle.
-        config_map.reinit(start);
-        (0..wraps).for_each(|_| {
-            config_map.step();
-        });
-        let cycle_steps = config_map.count();
-
-        // Proof that there is a cycle:
-        for _ in 0..10 {
-            (0..wraps).for_each(|_| {
-                config_map.step();
-            });
-            assert_eq!(cycle_steps, config_map.count());
+        match name {
+            "simulate" => {
+                let steps = export.steps_or(1000);
+                let reachable = InfiniteGarden::new(&field).simulate(start, steps as usize);
+                let tiled = match count_tiled(&field, start, steps) {
+                    Ok(count) => count.to_string(),
+                    Err(e) => format!("{e}"),
+                };
+                export.write(
+                    &format!("day21-{steps}.txt"),
+                    &format!(
+                        "{} plots reachable in {steps} steps, {} in the start tile (tiled solver: {tiled})\n{reachable}",
+                        reachable.total,
+                        reachable.tile(0, 0),
+                    ),
+                )
+            }
+            "distances" => {
+                let map = field.distance_map(start);
+                export.write(
+                    "day21-distances.txt",
+                    &render_distances(&field, &map, export.steps_or(64)),
+                )
+            }
+            _ => bail!("unknown export {name}"),
         }
-
-        // Reinitialize to count the steps in a full cycle.
-        config_map.reinit(start);
-        (0..rem).for_each(|_| {
-            config_map.step();
-        });
-        let rem_steps = config_map.count();
-
-        // Complete guesswork under the assumption that wrapping doesn't matter.
-        // It doesn't work.
-        (cycle_steps * div + rem_steps * div) as u64
     }
 }
 
@@ -250,17 +502,136 @@ mod tests {
 
     use super::*;
 
+    /// The example with the row and column of the start cleared.
+    const TEST_CASE_CLEAR: &str = textwrap_macros::dedent!(
+        r"
+    ...........
+    ......##.#.
+    .###..#..#.
+    ..#.#...#..
+    ....#.#....
+    .....S.....
+    .##......#.
+    .......##..
+    .##.#.####.
+    .##...#.##.
+    ...........
+    "
+    );
+
     #[test]
-    fn test_parse() {
+    fn test_count_tiled() {
+        let rectangles = [
+            ".......\n.#...#.\n...S...\n..#.#..\n.......",
+            "......\n.##.#.\n...S..\n.#..#.\n......",
+        ];
+        for input in [TEST_CASE_CLEAR].into_iter().chain(rectangles) {
+            let (field, start) = Field::parse(input);
+            let garden = InfiniteGarden::new(&field);
+            for steps in 0..=100 {
+                assert_eq!(
+                    count_tiled(&field, start, steps as u64).unwrap(),
+                    garden.simulate(start, steps).total,
+                    "{steps} steps on\n{input}"
+                );
+            }
+        }
+
         let (field, start) = Field::parse(TEST_CASE);
-        let mut config_map = ConfigMap::new(field, start);
+        let err = count_tiled(&field, start, 6).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the row of the start has rocks, the infinite garden can't be solved by tiles"
+        );
+    }
 
-        for _ in 0..6 {
-            config_map.step();
-            println!("{}", config_map.current.0.len());
+    #[test]
+    fn test_infinite_garden() {
+        let (field, start) = Field::parse(TEST_CASE);
+        let garden = InfiniteGarden::new(&field);
+
+        let checkpoints = [
+            (6, 16),
+            (10, 50),
+            (50, 1594),
+            (100, 6536),
+            (500, 167004),
+            (1000, 668697),
+            (5000, 16733044),
+        ];
+        for (steps, expected) in checkpoints {
+            let reachable = garden.simulate(start, steps);
+            assert_eq!(reachable.total, expected, "{steps} steps");
+            assert_eq!(
+                reachable.tiles.iter().flatten().sum::<u64>(),
+                reachable.total
+            );
         }
 
-        assert_eq!(config_map.count(), 16);
+        let reachable = garden.simulate(start, 10);
+        assert_eq!(reachable.origin, (-1, -1));
+        assert_eq!(reachable.to_string(), " .  2  .\n 7 33  3\n .  5  .\n");
+        assert_eq!(reachable.tile(1, 0), 3);
+        assert_eq!(reachable.tile(2, 0), 0);
+    }
+
+    /// Plots reachable in the tile after each step, up to `steps`, stepping from every plot
+    /// reached so far.
+    fn step_by_step(field: &Field, starts: &[Point], steps: u64) -> Vec<u64> {
+        let mut current = starts.iter().copied().collect::<BTreeSet<_>>();
+        let mut counts = vec![current.len() as u64];
+        for _ in 0..steps {
+            current = current
+                .iter()
+                .flat_map(|p| {
+                    [
+                        Direction::North,
+                        Direction::South,
+                        Direction::West,
+                        Direction::East,
+                    ]
+                    .map(|d| p.add(d, field.width, field.height))
+                })
+                .flatten()
+                .filter(|p| field.points.contains(p))
+                .collect();
+            counts.push(current.len() as u64);
+        }
+        counts
+    }
+
+    #[test]
+    fn test_distance_map() {
+        let (field, start) = Field::parse(TEST_CASE);
+        let map = field.distance_map(start);
+        assert_eq!(map.reachable(6), 16);
+        assert_eq!(map.distance(start), Some(0));
+        assert_eq!(map.distance(Point(4, 5)), Some(1));
+        assert_eq!(map.distance(Point(5, 4)), Some(1));
+        assert_eq!(map.distance(Point(4, 4)), None);
+
+        let steps = (0..40).collect_vec();
+        assert_eq!(
+            map.reachable_all(&steps),
+            step_by_step(&field, &[start], 39)
+        );
+
+        // Starts of both colours reach every plot at both parities.
+        let starts = [Point(0, 0), Point(1, 0)];
+        let map = field.distance_map_from(&starts);
+        assert_eq!(map.distance(Point(1, 1)), Some(1));
+        assert_eq!(map.reachable_all(&steps), step_by_step(&field, &starts, 39));
+
+        let (field, start) = Field::parse(TEST_CASE);
+        let rendered = render_distances(&field, &field.distance_map(start), 6);
+        let lines = rendered.lines().collect_vec();
+        assert_eq!(lines[5], "7##210####9");
+        assert!(lines
+            .last()
+            .unwrap()
+            .split('|')
+            .map(str::trim)
+            .eq(["6", "16"]));
     }
 }
 
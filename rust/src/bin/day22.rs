@@ -1,7 +1,10 @@
-use adventofcode2023::AocSolution;
+use adventofcode2023::{export::Export, format_table, AocSolution};
 
+#[cfg(test)]
 use std::collections::VecDeque;
+use std::{collections::BTreeSet, fmt::Write};
 
+use anyhow::{anyhow, bail, Context, Result};
 use hashbrown::{HashMap, HashSet};
 use itertools::Itertools;
 
@@ -11,7 +14,7 @@ struct Point(usize, usize, usize);
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 struct BrickId(usize);
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 struct Brick {
     start: Point,
     end: Point,
@@ -35,44 +38,73 @@ impl Brick {
     }
 }
 
-fn parse(input: &str) -> Vec<Brick> {
-    let mut v: Vec<_> = input
-        .trim()
-        .lines()
-        .map(|line| {
-            let (start, end) = line.split('~').next_tuple().unwrap();
-
-            let (x, y, z) = start
-                .split(',')
-                .map(|s| s.parse::<usize>().unwrap())
-                .next_tuple()
-                .unwrap();
-            let start = Point(x, y, z);
-
-            let (x, y, z) = end
-                .split(',')
-                .map(|s| s.parse::<usize>().unwrap())
-                .next_tuple()
-                .unwrap();
-            let end = Point(x, y, z);
-
-            Brick {
-                start,
-                end,
-                id: BrickId(0),
-            }
-        })
-        .collect();
+fn parse_point(s: &str) -> Result<Point> {
+    let coords = s
+        .split(',')
+        .map(|c| c.trim().parse::<usize>().map_err(|e| anyhow!("{c:?}: {e}")))
+        .collect::<Result<Vec<_>>>()?;
+    let [x, y, z] = coords[..] else {
+        bail!("{s:?} should have 3 coordinates");
+    };
+    Ok(Point(x, y, z))
+}
 
-    // From the input, looks like x and y inside a brick are ordered,
-    // but z between bricks is not ordered.
-    v.sort_by(|a, b| a.start.2.cmp(&(b.start.2)));
+/// Reads the bricks, lowest first, with their ends in increasing order on every axis.
+///
+/// Bricks below the ground or overlapping each other are rejected, with the lines they come
+/// from, rather than corrupting the stack.
+fn parse(input: &str) -> Result<Vec<Brick>> {
+    let mut bricks = Vec::new();
+    for (n, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line_no = n + 1;
+
+        let Some((start, end)) = line.split_once('~') else {
+            bail!("line {line_no}: no `~` in {line:?}");
+        };
+        let start = parse_point(start).with_context(|| format!("line {line_no}"))?;
+        let end = parse_point(end).with_context(|| format!("line {line_no}"))?;
+
+        let brick = Brick {
+            start: Point(start.0.min(end.0), start.1.min(end.1), start.2.min(end.2)),
+            end: Point(start.0.max(end.0), start.1.max(end.1), start.2.max(end.2)),
+            id: BrickId(0),
+        };
+        if brick.start.2 < 1 {
+            bail!("line {line_no}: {line:?} goes below the ground at z = 0");
+        }
+        bricks.push((line_no, brick));
+    }
 
-    for (id, brick) in v.iter_mut().enumerate() {
+    // Every brick in each cell, so that all the pairs sharing it are reported.
+    let mut cells = HashMap::<_, Vec<usize>>::new();
+    let mut overlaps = BTreeSet::new();
+    for (line_no, brick) in &bricks {
+        for p in brick.range() {
+            let owners = cells.entry(p).or_default();
+            overlaps.extend(owners.iter().map(|&other| (other, *line_no)));
+            owners.push(*line_no);
+        }
+    }
+    if !overlaps.is_empty() {
+        let pairs = overlaps
+            .iter()
+            .map(|(a, b)| format!("lines {a} and {b}"))
+            .join(", ");
+        bail!("overlapping bricks on {pairs}");
+    }
+
+    // z between bricks is not ordered in the input.
+    let mut bricks = bricks.into_iter().map(|(_, brick)| brick).collect_vec();
+    bricks.sort_by_key(|brick| brick.start.2);
+
+    for (id, brick) in bricks.iter_mut().enumerate() {
         brick.id = BrickId(id);
     }
 
-    v
+    Ok(bricks)
 }
 
 #[derive(Clone, Debug)]
@@ -96,22 +128,26 @@ impl From<Vec<Brick>> for Field {
 }
 
 impl Field {
+    #[cfg(test)]
     fn add_brick(&mut self, brick: &Brick) {
         for p in brick.range() {
             self.field.insert(p, brick.id);
         }
     }
 
+    #[cfg(test)]
     fn remove_brick(&mut self, brick: &Brick) {
         for p in brick.range() {
             self.field.remove(&p);
         }
     }
 
+    #[cfg(test)]
     fn replace_brick(&mut self, old: Brick, new: Brick) {
         *self.bricks.iter_mut().find(|b| b.id == old.id).unwrap() = new;
     }
 
+    #[cfg(test)]
     fn support_map(&self) -> HashMap<BrickId, HashSet<BrickId>> {
         let mut support_map: HashMap<BrickId, HashSet<BrickId>> = HashMap::new();
 
@@ -143,6 +179,19 @@ impl Field {
             .filter(|(a, b)| a != b)
     }
 
+    fn chain_reactions(&self) -> ChainReactions {
+        let mut supporters = vec![Vec::new(); self.bricks.len()];
+        for (a, b) in self.find_supports() {
+            if !supporters[a.0].contains(&b.0) {
+                supporters[a.0].push(b.0);
+            }
+        }
+        ChainReactions::new(&self.bricks, supporters)
+    }
+
+    /// The bricks that fall if `id` is disintegrated, found by removing it and propagating
+    /// through the supports one brick at a time.
+    #[cfg(test)]
     fn find_chain(&self, id: BrickId) -> HashSet<BrickId> {
         let mut q = VecDeque::new();
         q.push_back(id);
@@ -169,6 +218,37 @@ impl Field {
         falling
     }
 
+    /// Drops every brick to where it comes to rest. The bricks are taken from the lowest up,
+    /// and each one falls in a single step onto the highest cube under its footprint.
+    fn settle(&mut self) {
+        let mut heights = HashMap::<(usize, usize), usize>::new();
+        let order = (0..self.bricks.len())
+            .sorted_by_key(|&i| self.bricks[i].start.2)
+            .collect_vec();
+
+        for i in order {
+            let brick = &mut self.bricks[i];
+            let footprint =
+                (brick.start.0..=brick.end.0).cartesian_product(brick.start.1..=brick.end.1);
+            let floor = footprint
+                .clone()
+                .filter_map(|xy| heights.get(&xy).copied())
+                .max()
+                .unwrap_or(0);
+
+            let drop = brick.start.2 - (floor + 1);
+            brick.start.2 -= drop;
+            brick.end.2 -= drop;
+            for xy in footprint {
+                heights.insert(xy, brick.end.2);
+            }
+        }
+
+        *self = Field::from(std::mem::take(&mut self.bricks));
+    }
+
+    /// Lowers the bricks one unit at a time until none can move.
+    #[cfg(test)]
     fn lower_bricks(&mut self) {
         loop {
             let bricks_to_lower = self
@@ -178,7 +258,7 @@ impl Field {
                     let lowered_brick = brick.lower();
                     if lowered_brick
                         .range()
-                        .all(|p| !self.field.get(&p).is_some_and(|&id| id != brick.id))
+                        .all(|p| self.field.get(&p).is_none_or(|&id| id == brick.id))
                         && lowered_brick.start.2 > 0
                         && lowered_brick.end.2 > 0
                     {
@@ -202,6 +282,8 @@ impl Field {
         }
     }
 
+    /// Bricks that can be disintegrated safely, checked directly on the supports.
+    #[cfg(test)]
     fn get_redundants(self: &Field) -> Vec<BrickId> {
         let support_map = self.support_map();
         support_map
@@ -216,33 +298,351 @@ impl Field {
     }
 }
 
+/// Which bricks hold up which, as a dominator tree of the support graph rooted at the ground.
+/// A brick dominates another when every chain of supports from the ground to the other goes
+/// through it, that is when the other falls if it is disintegrated.
+#[derive(Debug)]
+struct ChainReactions {
+    /// For each brick, the bricks it rests on; empty for the ones on the ground.
+    supporters: Vec<Vec<usize>>,
+    /// The bricks from the lowest bottom up, so that supporters come first.
+    order: Vec<usize>,
+    /// How many other bricks fall when each brick is disintegrated.
+    falls: Vec<usize>,
+}
+
+impl ChainReactions {
+    fn new(bricks: &[Brick], supporters: Vec<Vec<usize>>) -> Self {
+        let order = (0..bricks.len())
+            .sorted_by_key(|&i| bricks[i].start.2)
+            .collect_vec();
+
+        // Immediate dominator of each brick, `None` for the ground.
+        let mut idom = vec![None; bricks.len()];
+        let mut depth = vec![0; bricks.len()];
+        for &i in &order {
+            // A brick depends on the closest brick all of its supporters depend on, or only
+            // on the ground if they have none in common.
+            idom[i] = supporters[i]
+                .iter()
+                .map(|&s| Some(s))
+                .reduce(|a, b| Self::common_dominator(&idom, &depth, a, b))
+                .flatten();
+            depth[i] = idom[i].map_or(0, |d| depth[d]) + 1;
+        }
+
+        let mut falls = vec![0; bricks.len()];
+        for &i in order.iter().rev() {
+            if let Some(d) = idom[i] {
+                falls[d] += falls[i] + 1;
+            }
+        }
+
+        Self {
+            supporters,
+            order,
+            falls,
+        }
+    }
+
+    fn common_dominator(
+        idom: &[Option<usize>],
+        depth: &[usize],
+        mut a: Option<usize>,
+        mut b: Option<usize>,
+    ) -> Option<usize> {
+        let depth = |x: Option<usize>| x.map_or(0, |i| depth[i]);
+        while a != b {
+            if depth(a) >= depth(b) {
+                a = a.and_then(|i| idom[i]);
+            } else {
+                b = b.and_then(|i| idom[i]);
+            }
+        }
+        a
+    }
+
+    fn falls(&self, id: BrickId) -> usize {
+        self.falls[id.0]
+    }
+
+    /// Bricks that can be disintegrated without anything else falling.
+    fn safe(&self) -> impl Iterator<Item = BrickId> + '_ {
+        (0..self.falls.len())
+            .filter(|&i| self.falls[i] == 0)
+            .map(BrickId)
+    }
+
+    /// The brick whose removal makes the most others fall, and how many.
+    fn most_load_bearing(&self) -> Option<(BrickId, usize)> {
+        (0..self.falls.len())
+            .max_by_key(|&i| (self.falls[i], std::cmp::Reverse(i)))
+            .map(|i| (BrickId(i), self.falls[i]))
+    }
+
+    /// The bricks that fall when all of `removed` are disintegrated at once. Several bricks
+    /// can hold up one together, so this walks the supports rather than the dominator tree.
+    fn falling(&self, removed: &[BrickId]) -> Vec<BrickId> {
+        let mut standing = vec![true; self.falls.len()];
+        for id in removed {
+            standing[id.0] = false;
+        }
+
+        let mut falling = Vec::new();
+        for &i in &self.order {
+            if !standing[i] {
+                continue;
+            }
+            let supporters = &self.supporters[i];
+            if !supporters.is_empty() && supporters.iter().all(|&s| !standing[s]) {
+                standing[i] = false;
+                falling.push(BrickId(i));
+            }
+        }
+
+        falling.sort_unstable();
+        falling
+    }
+}
+
+/// The most load-bearing brick and the bricks falling with it, then every brick that makes
+/// others fall, the most load-bearing first.
+fn render_chains(field: &Field, reactions: &ChainReactions) -> String {
+    let position = |id: BrickId| {
+        let Brick { start, end, .. } = field.bricks[id.0];
+        format!(
+            "{},{},{}~{},{},{}",
+            start.0, start.1, start.2, end.0, end.1, end.2
+        )
+    };
+
+    let mut out = String::new();
+    if let Some((id, falls)) = reactions.most_load_bearing() {
+        let falling = reactions.falling(&[id]).iter().map(|b| b.0).join(" ");
+        writeln!(
+            out,
+            "most load-bearing: brick {} at {}, {falls} others fall: {falling}\n",
+            id.0,
+            position(id)
+        )
+        .unwrap();
+    }
+
+    let rows = field
+        .bricks
+        .iter()
+        .map(|b| (b.id, reactions.falls(b.id)))
+        .filter(|&(_, falls)| falls > 0)
+        .sorted_by_key(|&(id, falls)| (std::cmp::Reverse(falls), id))
+        .map(|(id, falls)| [id.0.to_string(), position(id), falls.to_string()])
+        .collect_vec();
+    out + &format_table(&["Brick", "Position", "Falls"], &rows)
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Axis {
+    X,
+    Y,
+}
+
+/// How to colour the bricks in the 3D export.
+#[derive(Clone, Copy, Debug)]
+enum Colouring {
+    /// Green for bricks that can be disintegrated safely, red for the others.
+    Safety,
+    /// From white to red with the number of bricks that fall along.
+    ChainSize,
+}
+
+/// The corners of a box, indexed by `x + 2 * y + 4 * z` for the low (0) or high (1) sides,
+/// as quads facing outwards.
+const BOX_FACES: [[usize; 4]; 6] = [
+    [0, 2, 3, 1],
+    [4, 5, 7, 6],
+    [0, 1, 5, 4],
+    [2, 6, 7, 3],
+    [0, 4, 6, 2],
+    [1, 3, 7, 5],
+];
+
+// Views for inspecting the stack.
+impl Field {
+    /// Side view of the stack like the puzzle text draws it, looking along `axis`'s
+    /// perpendicular: `.` for empty cells, the brick's letter when only one is in the line of
+    /// sight, `?` when there are several.
+    fn projection(&self, axis: Axis) -> String {
+        let horizontal = |p: &Point| match axis {
+            Axis::X => p.0,
+            Axis::Y => p.1,
+        };
+        let width = self
+            .bricks
+            .iter()
+            .map(|b| horizontal(&b.end) + 1)
+            .max()
+            .unwrap_or(0);
+        let height = self.bricks.iter().map(|b| b.end.2).max().unwrap_or(0);
+
+        let mut seen = vec![vec![HashSet::new(); width]; height + 1];
+        for (p, id) in &self.field {
+            seen[p.2][horizontal(p)].insert(*id);
+        }
+
+        let name = match axis {
+            Axis::X => "x",
+            Axis::Y => "y",
+        };
+        let mut out = format!("{:>w$}\n", name, w = width / 2 + 1);
+        out += &(0..width)
+            .map(|i| (b'0' + (i % 10) as u8) as char)
+            .collect::<String>();
+        out.push('\n');
+
+        for z in (1..=height).rev() {
+            let row = seen[z]
+                .iter()
+                .map(|ids| match ids.len() {
+                    0 => '.',
+                    1 => (b'A' + (ids.iter().next().unwrap().0 % 26) as u8) as char,
+                    _ => '?',
+                })
+                .collect::<String>();
+            let label = if z == height.div_ceil(2) { " z" } else { "" };
+            writeln!(out, "{row} {z}{label}").unwrap();
+        }
+        writeln!(out, "{} 0", "-".repeat(width)).unwrap();
+
+        out
+    }
+
+    /// The settled stack as a Wavefront OBJ mesh, one box per brick with z going up, along
+    /// with the material library it refers to as `mtllib`.
+    fn to_obj(&self, colouring: Colouring, mtllib: &str) -> (String, String) {
+        let reactions = self.chain_reactions();
+        let max_falls = self.bricks.iter().map(|b| reactions.falls(b.id)).max();
+
+        let colour = |id: BrickId| -> [u8; 3] {
+            let falls = reactions.falls(id);
+            match colouring {
+                Colouring::Safety if falls == 0 => [0x2a, 0x9d, 0x8f],
+                Colouring::Safety => [0xe7, 0x6f, 0x51],
+                Colouring::ChainSize => {
+                    let fade = 255 - (255 * falls / max_falls.unwrap_or(0).max(1)) as u8;
+                    [255, fade, fade]
+                }
+            }
+        };
+
+        let mut obj = format!("mtllib {mtllib}\n");
+        let mut materials = BTreeSet::new();
+
+        for (n, brick) in self.bricks.iter().enumerate() {
+            let [r, g, b] = colour(brick.id);
+            let material = format!("c{r:02x}{g:02x}{b:02x}");
+
+            writeln!(obj, "o brick{}", brick.id.0).unwrap();
+            for corner in 0..8 {
+                let pick = |bit: usize, low: usize, high: usize| {
+                    if corner >> bit & 1 == 0 {
+                        low
+                    } else {
+                        high + 1
+                    }
+                };
+                let (s, e) = (brick.start, brick.end);
+                let (x, y, z) = (pick(0, s.0, e.0), pick(1, s.1, e.1), pick(2, s.2, e.2));
+                writeln!(obj, "v {x} {y} {z}").unwrap();
+            }
+
+            writeln!(obj, "usemtl {material}").unwrap();
+            for face in BOX_FACES {
+                let [a, b, c, d] = face.map(|i| n * 8 + i + 1);
+                writeln!(obj, "f {a} {b} {c} {d}").unwrap();
+            }
+
+            materials.insert((material, [r, g, b]));
+        }
+
+        let mut mtl = String::new();
+        for (material, rgb) in materials {
+            let [r, g, b] = rgb.map(|c| c as f64 / 255.0);
+            writeln!(mtl, "newmtl {material}\nKd {r:.3} {g:.3} {b:.3}").unwrap();
+        }
+
+        (obj, mtl)
+    }
+}
+
 pub struct Solution;
 
 impl AocSolution for Solution {
     const DAY: u8 = 22;
+    const EXPORTS: &'static [(&'static str, &'static str)] = &[
+        (
+            "obj",
+            "settled stack as an OBJ mesh, safe bricks green and the others red",
+        ),
+        (
+            "obj-chains",
+            "the same mesh, redder with the number of bricks that would fall",
+        ),
+        (
+            "chains",
+            "the most load-bearing brick, and how many bricks fall with each of the others",
+        ),
+        (
+            "side",
+            "x-z and y-z side views of the settled stack, like the puzzle text",
+        ),
+    ];
 
     fn new() -> Self {
         Self
     }
 
     fn part1(&self, input: &str) -> u64 {
-        let bricks = parse(input);
+        let bricks = parse(input).unwrap();
         let mut field = Field::from(bricks);
-        field.lower_bricks();
-        field.get_redundants().len() as u64
+        field.settle();
+        field.chain_reactions().safe().count() as u64
     }
 
     fn part2(&self, input: &str) -> u64 {
-        let bricks = parse(input);
+        let bricks = parse(input).unwrap();
         let mut field = Field::from(bricks);
-        field.lower_bricks();
+        field.settle();
 
+        let reactions = field.chain_reactions();
         field
             .bricks
             .iter()
-            .map(|b| field.find_chain(b.id).len() as u64)
+            .map(|b| reactions.falls(b.id) as u64)
             .sum::<u64>()
     }
+
+    fn export(&self, input: &str, name: &str, export: &Export) -> Result<()> {
+        let mut field = Field::from(parse(input)?);
+        field.settle();
+
+        let (colouring, file) = match name {
+            "obj" => (Colouring::Safety, "day22"),
+            "obj-chains" => (Colouring::ChainSize, "day22-chains"),
+            "side" => {
+                let views = field.projection(Axis::X) + "\n" + &field.projection(Axis::Y);
+                return export.write("day22.txt", &views);
+            }
+            "chains" => {
+                let chains = render_chains(&field, &field.chain_reactions());
+                return export.write("day22-chains.txt", &chains);
+            }
+            _ => bail!("unknown export {name}"),
+        };
+
+        let mtllib = format!("{file}.mtl");
+        let (obj, mtl) = field.to_obj(colouring, &mtllib);
+        export.write(&format!("{file}.obj"), &obj)?;
+        export.write(&mtllib, &mtl)
+    }
 }
 
 #[cfg(test)]
@@ -268,13 +668,233 @@ mod tests {
 
     #[test]
     fn test_part2() {
-        let bricks = parse(TEST_CASE);
+        let bricks = parse(TEST_CASE).unwrap();
         let mut field = Field::from(bricks);
         field.lower_bricks();
 
         println!("{:?}", field.find_chain(BrickId(0)));
         println!("{:?}", field.find_chain(BrickId(5)));
     }
+
+    #[test]
+    fn test_chain_reactions() {
+        let mut field = Field::from(parse(TEST_CASE).unwrap());
+        field.settle();
+        let reactions = field.chain_reactions();
+
+        assert_eq!(reactions.falls(BrickId(0)), 6);
+        assert_eq!(reactions.falls(BrickId(5)), 1);
+        assert_eq!(reactions.most_load_bearing(), Some((BrickId(0), 6)));
+        assert_eq!(
+            reactions.safe().collect_vec(),
+            [BrickId(1), BrickId(2), BrickId(3), BrickId(4), BrickId(6)]
+        );
+
+        // B and C hold up D and E together, and everything above falls with them.
+        let falling = reactions.falling(&[BrickId(1), BrickId(2)]);
+        assert_eq!(falling, [BrickId(3), BrickId(4), BrickId(5), BrickId(6)]);
+        assert!(reactions.falling(&[BrickId(3)]).is_empty());
+
+        let chains = render_chains(&field, &reactions);
+        let lines = chains.lines().collect_vec();
+        assert_eq!(
+            lines[0],
+            "most load-bearing: brick 0 at 1,0,1~1,2,1, 6 others fall: 1 2 3 4 5 6"
+        );
+        let rows = lines[4..]
+            .iter()
+            .map(|line| line.split('|').map(str::trim).collect_vec())
+            .collect_vec();
+        assert_eq!(rows, [["0", "1,0,1~1,2,1", "6"], ["5", "0,1,4~2,1,4", "1"]]);
+
+        let mut field = Field::from(parse(&random_stack(150, 100)).unwrap());
+        field.settle();
+        let reactions = field.chain_reactions();
+        let redundants = field.get_redundants().into_iter().sorted().collect_vec();
+        assert_eq!(reactions.safe().collect_vec(), redundants);
+        for brick in &field.bricks {
+            let chain = field.find_chain(brick.id);
+            assert_eq!(reactions.falls(brick.id), chain.len());
+            assert_eq!(
+                reactions.falling(&[brick.id]),
+                chain.into_iter().sorted().collect_vec()
+            );
+        }
+    }
+
+    #[test]
+    fn test_projection() {
+        let field = Field::from(parse(TEST_CASE).unwrap());
+        let xz = textwrap_macros::dedent!(
+            "
+             x
+            012
+            .G. 9
+            .G. 8
+            ... 7
+            FFF 6
+            ..E 5 z
+            D.. 4
+            CCC 3
+            BBB 2
+            .A. 1
+            --- 0
+            "
+        );
+        assert_eq!(field.projection(Axis::X), &xz[1..]);
+
+        let mut field = field;
+        field.settle();
+        let xz = textwrap_macros::dedent!(
+            "
+             x
+            012
+            .G. 6
+            .G. 5
+            FFF 4
+            D.E 3 z
+            ??? 2
+            .A. 1
+            --- 0
+            "
+        );
+        let yz = textwrap_macros::dedent!(
+            "
+             y
+            012
+            .G. 6
+            .G. 5
+            .F. 4
+            ??? 3 z
+            B.C 2
+            AAA 1
+            --- 0
+            "
+        );
+        assert_eq!(field.projection(Axis::X), &xz[1..]);
+        assert_eq!(field.projection(Axis::Y), &yz[1..]);
+    }
+
+    #[test]
+    fn test_to_obj() {
+        let mut field = Field::from(parse(TEST_CASE).unwrap());
+        field.settle();
+
+        let (obj, mtl) = field.to_obj(Colouring::Safety, "day22.mtl");
+        assert!(obj.starts_with("mtllib day22.mtl\no brick0\nv 1 0 1\nv 2 0 1\n"));
+        assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 7 * 8);
+        assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 7 * 6);
+        assert!(obj.contains("o brick6\nv 1 1 5\n"));
+        assert!(obj.ends_with("f 50 52 56 54\n"));
+        assert_eq!(
+            mtl,
+            "newmtl c2a9d8f\nKd 0.165 0.616 0.561\nnewmtl ce76f51\nKd 0.906 0.435 0.318\n"
+        );
+
+        // A, which brings down all 6 others, is the reddest.
+        let (obj, mtl) = field.to_obj(Colouring::ChainSize, "day22.mtl");
+        assert!(obj.contains("usemtl cff0000"));
+        assert_eq!(mtl.matches("newmtl").count(), 3);
+    }
+
+    #[test]
+    fn test_parse() {
+        let bricks = parse("1,2,5~1,0,5\n\n0,0,1~0,0,3\n").unwrap();
+        assert_eq!(
+            (bricks[0].start, bricks[0].end),
+            (Point(0, 0, 1), Point(0, 0, 3))
+        );
+        assert_eq!(
+            (bricks[1].start, bricks[1].end),
+            (Point(1, 0, 5), Point(1, 2, 5))
+        );
+        assert_eq!(bricks[1].range().count(), 3);
+
+        let error = |input| format!("{:#}", parse(input).unwrap_err());
+        assert_eq!(
+            error("0,0,1~0,0,2\n1,1,0~1,1,1"),
+            "line 2: \"1,1,0~1,1,1\" goes below the ground at z = 0"
+        );
+        assert_eq!(
+            error("0,0,1~0,0,2\n0,0,x~0,0,3"),
+            "line 2: \"x\": invalid digit found in string"
+        );
+        assert_eq!(
+            error("0,0,1~0,0"),
+            "line 1: \"0,0\" should have 3 coordinates"
+        );
+        assert_eq!(error("0,0,1"), "line 1: no `~` in \"0,0,1\"");
+        assert_eq!(
+            error("0,0,1~2,0,1\n5,5,5~5,5,6\n1,0,1~1,1,1\n0,0,3~0,0,1"),
+            "overlapping bricks on lines 1 and 3, lines 1 and 4"
+        );
+        assert_eq!(
+            error("0,0,1~0,0,1\n0,0,1~1,0,1\n0,0,1~0,1,1"),
+            "overlapping bricks on lines 1 and 2, lines 1 and 3, lines 2 and 3"
+        );
+    }
+
+    /// A pile of bricks dropped at random from up to `height`.
+    fn random_stack(count: usize, height: usize) -> String {
+        let mut seed = 0x2545f4914f6cdd1du64;
+        let mut next = |n: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as usize % n
+        };
+
+        let mut occupied = HashSet::new();
+        let mut lines = Vec::new();
+        while lines.len() < count {
+            let (x, y, z) = (next(6), next(6), 1 + next(height));
+            let len = next(4);
+            let end = match next(3) {
+                0 => (x + len, y, z),
+                1 => (x, y + len, z),
+                _ => (x, y, z + len),
+            };
+            let brick = Brick {
+                start: Point(x, y, z),
+                end: Point(end.0, end.1, end.2),
+                id: BrickId(0),
+            };
+            if brick.range().all(|p| !occupied.contains(&p)) {
+                occupied.extend(brick.range());
+                lines.push(format!("{x},{y},{z}~{},{},{}", end.0, end.1, end.2));
+            }
+        }
+        lines.join("\n")
+    }
+
+    #[test]
+    fn test_settle() {
+        let mut field = Field::from(parse(TEST_CASE).unwrap());
+        field.settle();
+        let reactions = field.chain_reactions();
+        assert_eq!(reactions.safe().count(), 5);
+        assert_eq!(
+            field
+                .bricks
+                .iter()
+                .map(|b| reactions.falls(b.id))
+                .sum::<usize>(),
+            7
+        );
+        assert_eq!(Solution.part1(TEST_CASE), 5);
+        assert_eq!(Solution.part2(TEST_CASE), 7);
+
+        for input in [TEST_CASE.to_string(), random_stack(150, 100)] {
+            let mut stepwise = Field::from(parse(&input).unwrap());
+            stepwise.lower_bricks();
+            let mut settled = Field::from(parse(&input).unwrap());
+            settled.settle();
+
+            assert_eq!(settled.bricks, stepwise.bricks);
+            assert_eq!(settled.field, stepwise.field);
+            assert_eq!(settled.support_map(), stepwise.support_map());
+        }
+    }
 }
 
 adventofcode2023::run!(Solution);
@@ -1,6 +1,6 @@
 use std::collections::VecDeque;
 
-use adventofcode2023::AocSolution;
+use adventofcode2023::{graph::WeightedGraph, AocSolution};
 use hashbrown::{HashMap, HashSet};
 use itertools::Itertools;
 
@@ -17,7 +17,11 @@ impl AocSolution for Solution {
     }
 
     fn part1(&self, input: &str) -> u64 {
-        Grid::parse(input).traverse()
+        let graph = Grid::parse(input).junction_graph(true);
+        let hike = graph
+            .longest_dag_hike()
+            .expect("the slopes should make the maze acyclic");
+        hike.length as u64
     }
 
     fn part2(&self, input: &str) -> u64 {
@@ -110,6 +114,8 @@ impl<'a> Grid<'a> {
             .filter(|&p| matches!(self.get(p), b'.' | b'<' | b'>' | b'v' | b'^'))
     }
 
+    /// Longest hike down the slopes, walking cell by cell.
+    #[cfg(test)]
     fn traverse(&self) -> u64 {
         let mut q = VecDeque::new();
         q.push_back((self.start(), Vec::<(usize, usize)>::new()));
@@ -134,32 +140,6 @@ impl<'a> Grid<'a> {
         paths.into_iter().map(|p| p.len()).max().unwrap() as u64
     }
 
-    fn distance(
-        &self,
-        a: (usize, usize),
-        b: (usize, usize),
-        branch_points: &HashSet<(usize, usize)>,
-    ) -> Option<usize> {
-        let mut q = VecDeque::new();
-        q.push_back((a, 0usize));
-
-        let mut visited = HashSet::new();
-
-        while let Some((head, len)) = q.pop_front() {
-            for edge in self.edges2(head) {
-                if edge == b {
-                    return Some(len + 1);
-                }
-                if !branch_points.contains(&edge) && !visited.contains(&edge) {
-                    q.push_back((edge, len + 1));
-                    visited.insert(edge);
-                }
-            }
-        }
-
-        None
-    }
-
     fn branch_points(&self) -> HashSet<(usize, usize)> {
         let adj_lists = self
             .points()
@@ -173,54 +153,163 @@ impl<'a> Grid<'a> {
             .collect::<HashSet<_>>()
     }
 
+    /// The junctions of the maze and the lengths of the corridors between them. With
+    /// `slopes`, corridors can only be walked down their slopes, which makes the graph of the
+    /// real inputs acyclic.
+    fn junction_graph(&self, slopes: bool) -> JunctionGraph {
+        let junctions = self.branch_points().into_iter().sorted();
+        let graph = if slopes {
+            WeightedGraph::from_maze(junctions, |p| self.edges(p))
+        } else {
+            WeightedGraph::from_maze(junctions, |p| self.edges2(p))
+        };
+
+        JunctionGraph {
+            start: graph.index(&self.start()).unwrap(),
+            end: graph.index(&self.end()).unwrap(),
+            graph,
+        }
+    }
+
     fn traverse2(&self) -> u64 {
-        let branch_points = self.branch_points();
+        self.junction_graph(false)
+            .longest_hike(true)
+            .unwrap()
+            .length as u64
+    }
+}
 
-        let distances = branch_points
-            .iter()
-            .cartesian_product(branch_points.iter())
-            .filter(|(a, b)| a != b)
-            .filter_map(|(a, b)| self.distance(*a, *b, &branch_points).map(|d| ((*a, *b), d)))
-            .collect::<HashMap<_, _>>();
+/// The junctions of the maze, with dense indices, and the corridors between them.
+#[derive(Debug)]
+struct JunctionGraph {
+    graph: WeightedGraph<(usize, usize)>,
+    start: usize,
+    end: usize,
+}
 
-        let edges = distances.keys().fold(
-            HashMap::<(usize, usize), HashSet<(usize, usize)>>::new(),
-            |mut o, (src, dst)| {
-                o.entry(*src).or_default().insert(*dst);
-                o
-            },
-        );
+/// The longest hike found, with the junctions it goes through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Hike {
+    length: usize,
+    path: Vec<(usize, usize)>,
+}
 
-        let mut q = VecDeque::new();
-        q.push_back((self.start(), vec![self.start()]));
-        let mut paths = Vec::new();
-        let end = self.end();
+struct Search<'g> {
+    graph: &'g JunctionGraph,
+    /// The only junction leading to the exit and the length of that corridor, when the walk
+    /// has to go straight to the exit once there.
+    last: Option<(usize, usize)>,
+    path: Vec<usize>,
+    best: Option<(usize, Vec<usize>)>,
+}
 
-        while let Some((head, path)) = q.pop_front() {
-            if head == end {
-                paths.push(path);
-                continue;
+impl Search<'_> {
+    fn dfs(&mut self, node: usize, visited: u64, length: usize) {
+        if node == self.graph.end {
+            if self.best.as_ref().is_none_or(|(best, _)| length > *best) {
+                self.best = Some((length, self.path.clone()));
             }
+            return;
+        }
 
-            for &edge in edges.get(&head).unwrap() {
-                if !path.contains(&edge) {
-                    let mut path = path.clone();
-                    path.push(edge);
-                    q.push_back((edge, path));
+        // Going anywhere else from the last junction would cut the exit off.
+        if let Some((last, to_end)) = self.last {
+            if node == last {
+                self.path.push(self.graph.end);
+                self.dfs(self.graph.end, visited, length + to_end);
+                self.path.pop();
+                return;
+            }
+        }
+
+        for &(next, len) in self.graph.graph.neighbours(node) {
+            if visited & 1 << next == 0 {
+                self.path.push(next);
+                self.dfs(next, visited | 1 << next, length + len);
+                self.path.pop();
+            }
+        }
+    }
+}
+
+impl JunctionGraph {
+    /// The longest hike from the start to the end when the corridors only go one way and
+    /// never loop, in linear time by relaxing the junctions in topological order. `None` if
+    /// there is a cycle or the end can't be reached.
+    fn longest_dag_hike(&self) -> Option<Hike> {
+        let n = self.graph.len();
+        let mut indegree = vec![0; n];
+        for &(j, _) in (0..n).flat_map(|i| self.graph.neighbours(i)) {
+            indegree[j] += 1;
+        }
+
+        let mut q = (0..n)
+            .filter(|&i| indegree[i] == 0)
+            .collect::<VecDeque<_>>();
+        let mut order = Vec::with_capacity(n);
+        while let Some(i) = q.pop_front() {
+            order.push(i);
+            for &(j, _) in self.graph.neighbours(i) {
+                indegree[j] -= 1;
+                if indegree[j] == 0 {
+                    q.push_back(j);
                 }
             }
         }
+        if order.len() != n {
+            return None;
+        }
 
-        paths
-            .into_iter()
-            .map(|path| {
-                path.iter()
-                    .tuple_windows()
-                    .map(|(&src, &dst)| distances.get(&(src, dst)).unwrap())
-                    .sum::<usize>()
-            })
-            .max()
-            .unwrap() as _
+        // The longest known hike to each junction, and the junction it comes from.
+        let mut best = vec![None; n];
+        best[self.start] = Some((0, self.start));
+        for i in order {
+            let Some((length, _)) = best[i] else {
+                continue;
+            };
+            for &(j, len) in self.graph.neighbours(i) {
+                if best[j].is_none_or(|(other, _)| length + len > other) {
+                    best[j] = Some((length + len, i));
+                }
+            }
+        }
+
+        let (length, _) = best[self.end]?;
+        let mut path = vec![self.graph.node(self.end)];
+        let mut node = self.end;
+        while node != self.start {
+            node = best[node].unwrap().1;
+            path.push(self.graph.node(node));
+        }
+        path.reverse();
+
+        Some(Hike { length, path })
+    }
+
+    /// The longest hike from the start to the end that never visits a junction twice, by a
+    /// depth-first search over the set of visited junctions as a bitmask.
+    ///
+    /// With `prune`, the search goes straight to the exit from the only junction leading
+    /// to it, which is the same hike but with far fewer branches to explore.
+    fn longest_hike(&self, prune: bool) -> Option<Hike> {
+        assert!(self.graph.len() <= 64, "too many junctions for a u64 mask");
+
+        let last = match *self.graph.neighbours(self.end) {
+            [(last, len)] if prune => Some((last, len)),
+            _ => None,
+        };
+        let mut search = Search {
+            graph: self,
+            last,
+            path: vec![self.start],
+            best: None,
+        };
+        search.dfs(self.start, 1 << self.start, 0);
+
+        search.best.map(|(length, path)| Hike {
+            length,
+            path: path.into_iter().map(|i| self.graph.node(i)).collect(),
+        })
     }
 }
 
@@ -277,11 +366,61 @@ mod tests {
     );
 
     #[test]
-    #[ignore]
     fn test_part1() {
         let g = Grid::parse(TEST_CASE);
-        println!("{:?} {:?}", g.start(), g.end());
-        println!("{}", g.traverse());
+        assert_eq!(g.traverse(), 94);
+        assert_eq!(Solution.part1(TEST_CASE), 94);
+    }
+
+    #[test]
+    fn test_longest_dag_hike() {
+        let g = Grid::parse(TEST_CASE);
+        let graph = g.junction_graph(true);
+        let undirected = g.junction_graph(false);
+        assert_eq!(graph.graph.nodes(), undirected.graph.nodes());
+        assert_eq!(graph.graph.edge_count(), 12);
+        assert_eq!(undirected.graph.edge_count(), 24);
+
+        let hike = graph.longest_dag_hike().unwrap();
+        assert_eq!(hike.length, 94);
+        assert_eq!(hike.path.first(), Some(&g.start()));
+        assert_eq!(hike.path.last(), Some(&g.end()));
+        assert_eq!(graph.longest_hike(true).unwrap(), hike);
+
+        assert!(undirected.longest_dag_hike().is_none());
+    }
+
+    #[test]
+    fn test_longest_hike() {
+        let g = Grid::parse(TEST_CASE);
+        let graph = g.junction_graph(false);
+        assert_eq!(graph.graph.len(), 9);
+
+        let hike = graph.longest_hike(true).unwrap();
+        assert_eq!(hike.length, 154);
+        assert_eq!(hike.path.first(), Some(&g.start()));
+        assert_eq!(hike.path.last(), Some(&g.end()));
+        assert!(hike.path.iter().all_unique());
+
+        // The path is made of corridors adding up to the length of the hike.
+        let index = |p| graph.graph.index(&p).unwrap();
+        let length = hike
+            .path
+            .iter()
+            .tuple_windows()
+            .map(|(&a, &b)| {
+                let (_, len) = graph
+                    .graph
+                    .neighbours(index(a))
+                    .iter()
+                    .find(|&&(j, _)| j == index(b))
+                    .unwrap();
+                len
+            })
+            .sum::<usize>();
+        assert_eq!(length, 154);
+
+        assert_eq!(graph.longest_hike(false).unwrap().length, 154);
     }
 
     #[test]
@@ -1,6 +1,13 @@
 use std::ops::RangeInclusive;
 
-use adventofcode2023::AocSolution;
+use adventofcode2023::{
+    export::Export,
+    format_table,
+    rational::{solve_linear, Rational},
+    AocSolution,
+};
+
+use anyhow::{anyhow, bail, Result};
 
 use itertools::Itertools;
 
@@ -8,6 +15,16 @@ struct Solution;
 
 impl AocSolution for Solution {
     const DAY: u8 = 24;
+    const EXPORTS: &'static [(&'static str, &'static str)] = &[
+        (
+            "approaches",
+            "closest approach of every pair of hailstones, earliest first",
+        ),
+        (
+            "collisions",
+            "pairs of hailstones that really collide, earliest first",
+        ),
+    ];
 
     fn new() -> Self
     where
@@ -17,41 +34,252 @@ impl AocSolution for Solution {
     }
 
     fn part1(&self, input: &str) -> u64 {
-        check_intersections2d(input, 200000000000000f64..=400000000000000f64) as u64
+        check_intersections2d(input, 200000000000000..=400000000000000) as u64
     }
 
     fn part2(&self, input: &str) -> u64 {
-        // Notes:
-        // There are 3*N parametric equations of the form x_i = vx_i * tx_i + x0_i (tx,ty,tz unk)
-        // Plus one parametric equation of the form x_r = vx_r * tx_r + x0_r for the rock (x,vx,x0 unk)
-        // But it should exist a unique (x0_r, vx_r) s.t. (x_r, tx_r) = (x_i, tx_i) for exactly one i
-        0
+        let throw = throw_rock(&parse(input)).unwrap();
+        throw.position.iter().sum::<i128>() as u64
     }
+
+    fn export(&self, input: &str, name: &str, export: &Export) -> Result<()> {
+        let hailstones = parse(input);
+        let approaches = match name {
+            "approaches" => approaches(&hailstones),
+            "collisions" => collisions(&hailstones),
+            _ => bail!("unknown export {name}"),
+        };
+        export.write(
+            &format!("day24-{name}.txt"),
+            &render_approaches(&approaches),
+        )
+    }
+}
+
+fn parse(input: &str) -> Vec<Hailstone> {
+    input.trim().lines().map(Hailstone::parse).collect()
 }
 
-fn check_intersections2d(input: &str, range: RangeInclusive<f64>) -> usize {
-    let hailstones = input
-        .trim()
-        .lines()
-        .map(Hailstone::parse)
-        .collect::<Vec<_>>();
+fn check_intersections2d(input: &str, range: RangeInclusive<i64>) -> usize {
+    let hailstones = parse(input);
+
+    let range = Rational::from(*range.start())..=Rational::from(*range.end());
 
     hailstones
         .iter()
         .enumerate()
-        .flat_map(|(idx, a)| hailstones[idx + 1..].iter().map(|b| (a.clone(), b.clone())))
-        .filter(|(a, b)| a.intersection2d(b, &range).is_some())
+        .flat_map(|(idx, a)| hailstones[idx + 1..].iter().map(move |b| (a, b)))
+        .filter(|(a, b)| matches!(a.intersection2d(b, &range), Crossing2d::Inside { .. }))
         .count()
 }
 
 #[derive(Debug, Clone, PartialEq)]
 struct Hailstone {
-    x: f64,
-    y: f64,
-    z: f64,
-    vx: f64,
-    vy: f64,
-    vz: f64,
+    x: i64,
+    y: i64,
+    z: i64,
+    vx: i64,
+    vy: i64,
+    vz: i64,
+}
+
+/// Which of the two hailstones would have had to go back in time to reach the crossing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Past {
+    A,
+    B,
+    Both,
+}
+
+/// How the XY paths of two hailstones relate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Crossing2d {
+    Inside { x: Rational, y: Rational },
+    Outside { x: Rational, y: Rational },
+    Past(Past),
+    Parallel,
+    Collinear,
+}
+
+/// A rock trajectory hitting every hailstone, and the time at which it hits each one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Throw {
+    position: [i128; 3],
+    velocity: [i128; 3],
+    times: Vec<i128>,
+}
+
+fn cross(a: [i128; 3], b: [i128; 3]) -> [i128; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn sub(a: [i128; 3], b: [i128; 3]) -> [i128; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [i128; 3], b: [i128; 3]) -> i128 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// The moment two hailstones are closest to each other, at or after time 0. The hailstones
+/// are indices into the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Approach {
+    a: usize,
+    b: usize,
+    time: Rational,
+    distance_squared: Rational,
+}
+
+impl Approach {
+    fn distance(&self) -> f64 {
+        self.distance_squared.to_f64().sqrt()
+    }
+
+    /// Whether the two hailstones are in the same place at the same time.
+    fn is_collision(&self) -> bool {
+        self.distance_squared.is_zero()
+    }
+}
+
+/// Closest approach of every pair of hailstones, earliest first.
+fn approaches(hailstones: &[Hailstone]) -> Vec<Approach> {
+    let mut approaches = hailstones
+        .iter()
+        .enumerate()
+        .tuple_combinations()
+        .map(|((a, ha), (b, hb))| {
+            let (time, distance_squared) = ha.closest_approach(hb);
+            Approach {
+                a,
+                b,
+                time,
+                distance_squared,
+            }
+        })
+        .collect_vec();
+
+    approaches.sort_by_key(|ap| (ap.time, ap.a, ap.b));
+    approaches
+}
+
+fn collisions(hailstones: &[Hailstone]) -> Vec<Approach> {
+    approaches(hailstones)
+        .into_iter()
+        .filter(Approach::is_collision)
+        .collect()
+}
+
+/// A table of the approaches, with the hailstones as line numbers of the input.
+fn render_approaches(approaches: &[Approach]) -> String {
+    let rows = approaches
+        .iter()
+        .map(|ap| {
+            [
+                (ap.a + 1).to_string(),
+                (ap.b + 1).to_string(),
+                ap.time.to_string(),
+                format!("{:.3}", ap.time.to_f64()),
+                format!("{:.3}", ap.distance()),
+            ]
+        })
+        .collect_vec();
+    format_table(&["A", "B", "Time", "", "Distance"], &rows)
+}
+
+/// For the rock (P, V) to hit hailstone i at some time, (P - p_i) x (V - v_i) = 0. The
+/// non-linear term P x V is the same for every hailstone, so subtracting the equations of two
+/// hailstones i, j gives three linear ones:
+///
+///   P x (v_j - v_i) + (p_j - p_i) x V = p_j x v_j - p_i x v_i
+///
+/// Two pairs give a 6x6 system in (X, Y, Z, VX, VY, VZ).
+fn pair_equations(a: &Hailstone, b: &Hailstone) -> [([Rational; 6], Rational); 3] {
+    let w = sub(b.vel(), a.vel());
+    let d = sub(b.pos(), a.pos());
+    let rhs = sub(cross(b.pos(), b.vel()), cross(a.pos(), a.vel()));
+
+    let rows = [
+        [0, w[2], -w[1], 0, -d[2], d[1]],
+        [-w[2], 0, w[0], d[2], 0, -d[0]],
+        [w[1], -w[0], 0, -d[1], d[0], 0],
+    ];
+
+    [0, 1, 2].map(|i| (rows[i].map(Rational::from), Rational::from(rhs[i])))
+}
+
+impl Throw {
+    /// The rock as a hailstone, if its trajectory fits the `i64` coordinates of one.
+    fn rock(&self) -> Result<Hailstone> {
+        let to_i64 = |v: [i128; 3]| -> Result<[i64; 3]> {
+            let [x, y, z] = v.map(|c| {
+                i64::try_from(c)
+                    .map_err(|_| anyhow!("the rock coordinate {c} doesn't fit in an i64"))
+            });
+            Ok([x?, y?, z?])
+        };
+        let [x, y, z] = to_i64(self.position)?;
+        let [vx, vy, vz] = to_i64(self.velocity)?;
+        Ok(Hailstone {
+            x,
+            y,
+            z,
+            vx,
+            vy,
+            vz,
+        })
+    }
+}
+
+fn throw_rock(hailstones: &[Hailstone]) -> Result<Throw> {
+    // Any three hailstones in general position determine the trajectory.
+    let solution = hailstones
+        .iter()
+        .tuple_combinations()
+        .find_map(|(a, b, c)| {
+            let (rows, rhs): (Vec<_>, Vec<_>) = pair_equations(a, b)
+                .into_iter()
+                .chain(pair_equations(a, c))
+                .map(|(row, rhs)| (row.to_vec(), rhs))
+                .unzip();
+            solve_linear(rows, rhs)
+        })
+        .ok_or_else(|| anyhow!("no three hailstones determine a unique trajectory"))?;
+
+    let Some(solution) = solution
+        .iter()
+        .map(Rational::to_integer)
+        .collect::<Option<Vec<_>>>()
+    else {
+        bail!("the only trajectory has non-integer coordinates: {solution:?}");
+    };
+
+    let mut throw = Throw {
+        position: [solution[0], solution[1], solution[2]],
+        velocity: [solution[3], solution[4], solution[5]],
+        times: Vec::new(),
+    };
+
+    let rock = throw.rock()?;
+    throw.times = hailstones
+        .iter()
+        .enumerate()
+        .map(|(idx, h)| match rock.closest_approach(h) {
+            (t, d) if d.is_zero() => t
+                .to_integer()
+                .ok_or_else(|| anyhow!("the rock hits hailstone {idx} at non-integer time {t}")),
+            (_, d) => bail!(
+                "the rock misses hailstone {idx} by {:.1}: {h:?}",
+                d.to_f64().sqrt()
+            ),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(throw)
 }
 
 impl Hailstone {
@@ -82,39 +310,81 @@ impl Hailstone {
         }
     }
 
-    fn plug(&self, t: f64) -> (f64, f64, f64) {
+    fn pos(&self) -> [i128; 3] {
+        [self.x as i128, self.y as i128, self.z as i128]
+    }
+
+    fn vel(&self) -> [i128; 3] {
+        [self.vx as i128, self.vy as i128, self.vz as i128]
+    }
+
+    /// Time and squared distance of the closest approach to `rhs`, not earlier than time 0.
+    fn closest_approach(&self, rhs: &Self) -> (Rational, Rational) {
+        // |dp + t dv|^2 is minimal at t = -(dp . dv) / |dv|^2, where it equals
+        // |dp x dv|^2 / |dv|^2. Going through the cross product keeps the numbers within i128.
+        let dp = sub(rhs.pos(), self.pos());
+        let dv = sub(rhs.vel(), self.vel());
+        let dv2 = dot(dv, dv);
+
+        if dv2 == 0 || dot(dp, dv) >= 0 {
+            // Not moving relative to each other, or already moving apart.
+            return (Rational::ZERO, Rational::from(dot(dp, dp)));
+        }
+
+        let c = cross(dp, dv);
         (
-            self.x + t * self.vx,
-            self.y + t * self.vy,
-            self.z + t * self.vz,
+            Rational::new(-dot(dp, dv), dv2),
+            Rational::new(dot(c, c), dv2),
         )
     }
 
-    fn intersection2d(&self, rhs: &Self, range: &RangeInclusive<f64>) -> Option<(f64, f64, f64)> {
-        // A.x + t A.vx = B.x + s B.vx, repeat for y and z, solve
+    fn plug(&self, t: Rational) -> (Rational, Rational, Rational) {
+        (
+            Rational::from(self.x) + t * Rational::from(self.vx),
+            Rational::from(self.y) + t * Rational::from(self.vy),
+            Rational::from(self.z) + t * Rational::from(self.vz),
+        )
+    }
+
+    fn intersection2d(&self, rhs: &Self, range: &RangeInclusive<Rational>) -> Crossing2d {
+        // A.x + t A.vx = B.x + s B.vx, repeat for y, solve
         // t = (B.x - A.x + s B.vx) / A.vx
         // t = (B.y - A.y + s B.vy) / A.vy
         // Derive s
         // A.vy (B.x - A.x + s B.vx) = A.vx (B.y - A.y + s B.vy)
         // s = (A.vy (B.x - A.x) - A.vx (B.y - A.y)) / (A.vx * B.vy - A.vy * B.vx)
+        // and symmetrically
+        // t = (B.vy (B.x - A.x) - B.vx (B.y - A.y)) / (A.vx * B.vy - A.vy * B.vx)
+
+        let (a, b) = (self, rhs);
+        let (dx, dy) = ((b.x - a.x) as i128, (b.y - a.y) as i128);
+        let den = a.vx as i128 * b.vy as i128 - a.vy as i128 * b.vx as i128;
 
-        let s = (self.vy * (rhs.x - self.x) - self.vx * (rhs.y - self.y))
-            / (self.vx * rhs.vy - self.vy * rhs.vx);
-        let t = (rhs.x - self.x + s * rhs.vx) / self.vx;
-
-        let pt = self.plug(t);
-        let ps = rhs.plug(s);
-
-        if range.contains(&pt.0)
-            && range.contains(&pt.1)
-            && range.contains(&ps.0)
-            && range.contains(&ps.1)
-            && t > 0.
-            && s > 0.
-        {
-            Some(pt)
+        if den == 0 {
+            // Same direction: the paths coincide if B's start lies on A's path.
+            return if a.vy as i128 * dx - a.vx as i128 * dy == 0 {
+                Crossing2d::Collinear
+            } else {
+                Crossing2d::Parallel
+            };
+        }
+
+        let s = Rational::new(a.vy as i128 * dx - a.vx as i128 * dy, den);
+        let t = Rational::new(b.vy as i128 * dx - b.vx as i128 * dy, den);
+
+        match (t.signum() < 0, s.signum() < 0) {
+            (true, true) => return Crossing2d::Past(Past::Both),
+            (true, false) => return Crossing2d::Past(Past::A),
+            (false, true) => return Crossing2d::Past(Past::B),
+            (false, false) => {}
+        }
+
+        let (x, y, _) = self.plug(t);
+
+        if range.contains(&x) && range.contains(&y) {
+            Crossing2d::Inside { x, y }
         } else {
-            None
+            Crossing2d::Outside { x, y }
         }
     }
 }
@@ -137,6 +407,129 @@ mod tests {
 
     #[test]
     fn test_part1() {
-        println!("{:?}", check_intersections2d(TEST_CASE, 7f64..=27f64));
+        assert_eq!(check_intersections2d(TEST_CASE, 7..=27), 2);
+    }
+
+    #[test]
+    fn test_intersection2d() {
+        let hs = TEST_CASE.trim().lines().map(Hailstone::parse).collect_vec();
+        let range = Rational::from(7i64)..=Rational::from(27i64);
+        let r = Rational::new;
+
+        let crossings = hs
+            .iter()
+            .tuple_combinations()
+            .map(|(a, b)| a.intersection2d(b, &range))
+            .collect_vec();
+
+        assert_eq!(
+            crossings,
+            [
+                Crossing2d::Inside {
+                    x: r(43, 3),
+                    y: r(46, 3)
+                },
+                Crossing2d::Inside {
+                    x: r(35, 3),
+                    y: r(50, 3)
+                },
+                Crossing2d::Outside {
+                    x: r(31, 5),
+                    y: r(97, 5)
+                },
+                Crossing2d::Past(Past::A),
+                Crossing2d::Parallel,
+                Crossing2d::Outside {
+                    x: r(-6, 1),
+                    y: r(-5, 1)
+                },
+                Crossing2d::Past(Past::Both),
+                Crossing2d::Outside {
+                    x: r(-2, 1),
+                    y: r(3, 1)
+                },
+                Crossing2d::Past(Past::B),
+                Crossing2d::Past(Past::Both),
+            ]
+        );
+
+        let a = Hailstone::parse("0, 0, 0 @ 1, 1, 1");
+        let b = Hailstone::parse("5, 5, 3 @ -2, -2, 0");
+        assert_eq!(a.intersection2d(&b, &range), Crossing2d::Collinear);
+    }
+
+    #[test]
+    fn test_part2() {
+        let throw = throw_rock(&parse(TEST_CASE)).unwrap();
+        assert_eq!(throw.position, [24, 13, 10]);
+        assert_eq!(throw.velocity, [-3, 1, 2]);
+        assert_eq!(throw.times, [5, 3, 4, 6, 1]);
+        assert_eq!(Solution.part2(TEST_CASE), 47);
+
+        let mut missed = parse(TEST_CASE);
+        missed.push(Hailstone::parse("0, 0, 0 @ 1, 1, 1"));
+        assert!(throw_rock(&missed).is_err());
+    }
+
+    #[test]
+    fn test_approaches() {
+        let hs = parse(TEST_CASE);
+        let approaches = approaches(&hs);
+
+        assert_eq!(approaches.len(), 10);
+        assert!(approaches.windows(2).all(|w| w[0].time <= w[1].time));
+        assert!(collisions(&hs).is_empty());
+
+        // The distance at the reported time is the reported distance.
+        for ap in &approaches {
+            let (a, b) = (hs[ap.a].plug(ap.time), hs[ap.b].plug(ap.time));
+            let d = [a.0 - b.0, a.1 - b.1, a.2 - b.2];
+            assert_eq!(d[0] * d[0] + d[1] * d[1] + d[2] * d[2], ap.distance_squared);
+        }
+
+        let find = |a, b| approaches.iter().find(|ap| (ap.a, ap.b) == (a, b)).unwrap();
+
+        let ab = find(0, 1);
+        assert_eq!(ab.time, Rational::new(13, 5));
+        assert_eq!(ab.distance_squared, Rational::new(336, 5));
+        assert!((ab.distance() - 8.1976).abs() < 1e-4);
+
+        // B and C travel along parallel paths, with C catching up.
+        let bc = find(1, 2);
+        assert_eq!(bc.time, Rational::new(16, 3));
+        assert_eq!(bc.distance_squared, Rational::new(40, 3));
+
+        let table = render_approaches(&approaches);
+        assert_eq!(table.lines().count(), 12);
+        assert!(table.lines().any(|line| line
+            .split('|')
+            .map(str::trim)
+            .eq(["1", "2", "13/5", "2.600", "8.198"])));
+    }
+
+    #[test]
+    fn test_rock_collisions() {
+        let mut hs = parse(TEST_CASE);
+        let throw = throw_rock(&hs).unwrap();
+        hs.push(throw.rock().unwrap());
+
+        let rock = hs.len() - 1;
+        let times = collisions(&hs)
+            .into_iter()
+            .map(|ap| {
+                assert_eq!(ap.b, rock);
+                (ap.a, ap.time.to_integer().unwrap())
+            })
+            .sorted()
+            .map(|(_, t)| t)
+            .collect_vec();
+
+        assert_eq!(times, throw.times);
+
+        let far = Throw {
+            position: [i64::MAX as i128 + 1, 0, 0],
+            ..throw
+        };
+        assert!(far.rock().is_err());
     }
 }
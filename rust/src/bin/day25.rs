@@ -1,14 +1,22 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, fmt::Write};
 
-use adventofcode2023::AocSolution;
+use adventofcode2023::{export::Export, AocSolution};
 
-use hashbrown::{HashMap, HashSet};
+use anyhow::{bail, Result};
+use hashbrown::HashMap;
 use itertools::Itertools;
 
 struct Solution;
 
 impl AocSolution for Solution {
     const DAY: u8 = 25;
+    const EXPORTS: &'static [(&'static str, &'static str)] = &[
+        (
+            "dot",
+            "Graphviz wiring diagram, with the cut and both components coloured",
+        ),
+        ("graphml", "the same diagram as GraphML"),
+    ];
 
     fn new() -> Self
     where
@@ -18,127 +26,274 @@ impl AocSolution for Solution {
     }
 
     fn part1(&self, input: &str) -> u64 {
-        AdjLists::parse(input).find_cut() as u64
+        AdjLists::parse(input).find_cut().unwrap() as u64
     }
 
     fn part2(&self, _input: &str) -> u64 {
         0
     }
+
+    fn export(&self, input: &str, name: &str, export: &Export) -> Result<()> {
+        let al = AdjLists::parse(input);
+        let cut = al.min_cut();
+        match name {
+            "dot" => export.write("day25.dot", &al.to_dot(Some(&cut))),
+            "graphml" => export.write("day25.graphml", &al.to_graphml(Some(&cut))),
+            _ => bail!("unknown export {name}"),
+        }
+    }
 }
 
+/// The wiring diagram, with node names interned to dense ids and the adjacency stored in
+/// compressed sparse rows: the neighbours of `a` are `targets[offsets[a]..offsets[a + 1]]`.
 #[derive(Debug, Clone)]
-struct AdjLists(HashMap<String, HashSet<String>>);
-
-impl AdjLists {
-    fn parse(input: &str) -> Self {
-        Self(
-            input
-                .trim()
-                .lines()
-                .flat_map(|line| {
-                    let (k, vs) = line.split(": ").next_tuple().unwrap();
-                    vs.split_whitespace()
-                        .map(String::from)
-                        .map(|v| (k.to_string(), v))
-                        .flat_map(|(k, v)| [(k.clone(), v.clone()), (v, k)])
-                })
-                .fold(HashMap::new(), |mut h, (k, v)| {
-                    h.entry(k.clone()).or_default().insert(v.clone());
-                    h.entry(v).or_default().insert(k);
-                    h
-                }),
-        )
-    }
-
-    fn most_traversed_edges(&self) -> Vec<((&str, &str), usize)> {
-        let mut traversed_count = HashMap::new();
-        for a in self.0.keys() {
+struct AdjLists<'a> {
+    names: Vec<&'a str>,
+    offsets: Vec<usize>,
+    targets: Vec<usize>,
+    /// Position in `targets` of the opposite direction of each arc.
+    reverse: Vec<usize>,
+}
+
+impl<'a> AdjLists<'a> {
+    fn parse(input: &'a str) -> Self {
+        let mut names = Vec::new();
+        let mut ids = HashMap::new();
+        let mut intern = |name: &'a str| {
+            *ids.entry(name).or_insert_with(|| {
+                names.push(name);
+                names.len() - 1
+            })
+        };
+
+        let edges = input
+            .trim()
+            .lines()
+            .flat_map(|line| {
+                let (k, vs) = line.split(": ").next_tuple().unwrap();
+                vs.split_whitespace().map(move |v| (k, v))
+            })
+            .map(|(k, v)| (intern(k), intern(v)))
+            .flat_map(|(a, b)| [(a, b), (b, a)])
+            .sorted()
+            .dedup()
+            .collect_vec();
+
+        let mut offsets = vec![0; names.len() + 1];
+        for &(a, _) in &edges {
+            offsets[a + 1] += 1;
+        }
+        for a in 0..names.len() {
+            offsets[a + 1] += offsets[a];
+        }
+
+        // Sorted by source then target, the arcs are already in CSR order.
+        let targets = edges.iter().map(|&(_, b)| b).collect_vec();
+        let reverse = edges
+            .iter()
+            .map(|&(a, b)| {
+                offsets[b]
+                    + targets[offsets[b]..offsets[b + 1]]
+                        .binary_search(&a)
+                        .unwrap()
+            })
+            .collect_vec();
+
+        Self {
+            names,
+            offsets,
+            targets,
+            reverse,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    fn neighbours(&self, a: usize) -> &[usize] {
+        &self.targets[self.offsets[a]..self.offsets[a + 1]]
+    }
+
+    /// Every edge once, as `(a, b)` with `a < b`.
+    fn edges(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.len()).flat_map(move |a| {
+            self.neighbours(a)
+                .iter()
+                .filter(move |&&b| a < b)
+                .map(move |&b| (a, b))
+        })
+    }
+
+    /// Exact global minimum cut. Every cut separates the first node `s` from some other node
+    /// `t`, so the smallest of the `s`-`t` max flows is the global minimum; the residual graph
+    /// of that flow gives the two sides.
+    fn min_cut(&self) -> Cut {
+        let s = 0;
+        let mut best: Option<(usize, Vec<bool>)> = None;
+
+        for t in 1..self.len() {
+            let limit = best.as_ref().map(|(flow, _)| *flow);
+            let (flow, side) = self.max_flow(s, t, limit);
+            if limit.map(|limit| flow < limit).unwrap_or(true) {
+                best = Some((flow, side));
+            }
+        }
+
+        let side = best.map(|(_, side)| side).unwrap_or_default();
+        let edges = self.edges().filter(|&(a, b)| side[a] != side[b]).collect();
+
+        Cut { edges, side }
+    }
+
+    /// Unit-capacity max flow from `s` to `t` with BFS augmenting paths, stopping once it
+    /// reaches `limit`. Returns the flow and which nodes are reachable from `s` in the residual
+    /// graph.
+    fn max_flow(&self, s: usize, t: usize, limit: Option<usize>) -> (usize, Vec<bool>) {
+        let mut flow_on = vec![0i8; self.targets.len()];
+        let mut flow = 0;
+
+        loop {
+            // The node and arc each node was reached from.
+            let mut prev = vec![None; self.len()];
             let mut q = VecDeque::new();
-            let mut visited = HashSet::new();
-            q.push_back(a.as_str());
-            visited.insert(a.as_str());
+            prev[s] = Some((s, usize::MAX));
+            q.push_back(s);
 
             while let Some(head) = q.pop_front() {
-                for b in self.0.get(head).unwrap() {
-                    if visited.insert(b.as_str()) {
-                        let k = if head.cmp(b).is_lt() {
-                            (head, b.as_str())
-                        } else {
-                            (b.as_str(), head)
-                        };
-
-                        *traversed_count.entry(k).or_default() += 1;
-                        q.push_back(b.as_str());
+                if head == t {
+                    break;
+                }
+                for (arc, &b) in (self.offsets[head]..).zip(self.neighbours(head)) {
+                    if prev[b].is_none() && flow_on[arc] < 1 {
+                        prev[b] = Some((head, arc));
+                        q.push_back(b);
                     }
                 }
             }
+
+            if prev[t].is_none() || limit.map(|limit| flow >= limit).unwrap_or(false) {
+                return (flow, prev.iter().map(Option::is_some).collect());
+            }
+
+            let mut b = t;
+            while b != s {
+                let (a, arc) = prev[b].unwrap();
+                flow_on[arc] += 1;
+                flow_on[self.reverse[arc]] -= 1;
+                b = a;
+            }
+            flow += 1;
         }
+    }
 
-        let mut most_traversed = traversed_count.into_iter().collect::<Vec<_>>();
-        most_traversed.sort_unstable_by_key(|(_, count)| *count);
-        most_traversed.reverse();
-        most_traversed
+    fn find_cut(&self) -> Result<usize> {
+        let cut = self.min_cut();
+
+        if cut.edges.len() != 3 {
+            bail!(
+                "the minimum cut has {} edges instead of 3: {:?}",
+                cut.edges.len(),
+                cut.edges
+                    .iter()
+                    .map(|&(a, b)| (self.names[a], self.names[b]))
+                    .collect_vec()
+            );
+        }
+
+        let [left, right] = cut.components().map(|c| c.len());
+        Ok(left * right)
     }
+}
+
+const COMPONENT_COLORS: [&str; 2] = ["#8ecae6", "#ffb703"];
 
-    fn compute_cut(&self, cut: HashSet<(&str, &str)>) -> Option<usize> {
-        let mut q = VecDeque::new();
-        let mut visited = HashSet::new();
-        let mut size = 1usize;
+// Exports for inspecting the wiring in external tools.
+impl AdjLists<'_> {
+    fn to_dot(&self, cut: Option<&Cut>) -> String {
+        let mut out = String::from("graph day25 {\n  node [style=filled];\n");
 
-        let start = self.0.keys().next().unwrap().as_str();
+        if let Some(cut) = cut {
+            for (a, name) in self.names.iter().enumerate() {
+                let color = COMPONENT_COLORS[cut.side[a] as usize];
+                writeln!(out, "  \"{name}\" [fillcolor=\"{color}\"];").unwrap();
+            }
+        }
 
-        q.push_back(start);
-        visited.insert(start);
+        for (a, b) in self.edges() {
+            let (na, nb) = (self.names[a], self.names[b]);
+            if cut.map(|cut| cut.contains(a, b)).unwrap_or(false) {
+                writeln!(out, "  \"{na}\" -- \"{nb}\" [color=red, penwidth=3];").unwrap();
+            } else {
+                writeln!(out, "  \"{na}\" -- \"{nb}\";").unwrap();
+            }
+        }
 
-        while let Some(head) = q.pop_front() {
-            for b in self.0.get(head).unwrap() {
-                let k = if head.cmp(b).is_lt() {
-                    (head, b.as_str())
-                } else {
-                    (b.as_str(), head)
-                };
+        out.push_str("}\n");
+        out
+    }
 
-                if cut.contains(&k) {
-                    continue;
-                }
+    fn to_graphml(&self, cut: Option<&Cut>) -> String {
+        let mut out = String::from(indoc::indoc! {r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+              <key id="component" for="node" attr.name="component" attr.type="int"/>
+              <key id="color" for="node" attr.name="color" attr.type="string"/>
+              <key id="cut" for="edge" attr.name="cut" attr.type="boolean">
+                <default>false</default>
+              </key>
+              <graph id="day25" edgedefault="undirected">
+        "#});
 
-                if visited.insert(b) {
-                    size += 1;
-                    q.push_back(b.as_str());
+        for (a, name) in self.names.iter().enumerate() {
+            match cut {
+                Some(cut) => {
+                    let component = cut.side[a] as usize;
+                    writeln!(
+                        out,
+                        "    <node id=\"{name}\"><data key=\"component\">{component}</data>\
+                         <data key=\"color\">{}</data></node>",
+                        COMPONENT_COLORS[component]
+                    )
+                    .unwrap();
                 }
+                None => writeln!(out, "    <node id=\"{name}\"/>").unwrap(),
             }
         }
 
-        if size < self.0.len() {
-            Some(size)
-        } else {
-            None
+        for (a, b) in self.edges() {
+            let (na, nb) = (self.names[a], self.names[b]);
+            if cut.map(|cut| cut.contains(a, b)).unwrap_or(false) {
+                writeln!(
+                    out,
+                    "    <edge source=\"{na}\" target=\"{nb}\"><data key=\"cut\">true</data></edge>"
+                )
+                .unwrap();
+            } else {
+                writeln!(out, "    <edge source=\"{na}\" target=\"{nb}\"/>").unwrap();
+            }
         }
+
+        out.push_str("  </graph>\n</graphml>\n");
+        out
     }
+}
 
-    fn find_cut(&self) -> usize {
-        let most_traversed = self
-            .most_traversed_edges()
-            .into_iter()
-            .map(|(k, _)| k)
-            .take(10)
-            .collect::<Vec<_>>();
+/// A set of edges whose removal splits the graph in two, and which side each node ends up on.
+#[derive(Debug)]
+struct Cut {
+    edges: Vec<(usize, usize)>,
+    side: Vec<bool>,
+}
 
-        let count = most_traversed
-            .iter()
-            .cartesian_product(most_traversed.iter())
-            .cartesian_product(most_traversed.iter())
-            .filter_map(|((e1, e2), e3)| {
-                if e1 != e2 && e2 != e3 && e1 != e3 {
-                    Some((e1, e2, e3))
-                } else {
-                    None
-                }
-            })
-            .find_map(|(e1, e2, e3)| self.compute_cut([*e1, *e2, *e3].into_iter().collect()))
-            .unwrap();
+impl Cut {
+    fn contains(&self, a: usize, b: usize) -> bool {
+        self.edges.contains(&(a.min(b), a.max(b)))
+    }
 
-        count * (self.0.len() - count)
+    fn components(&self) -> [Vec<usize>; 2] {
+        let (left, right) = (0..self.side.len()).partition(|&a| self.side[a]);
+        [left, right]
     }
 }
 
@@ -167,7 +322,86 @@ mod tests {
     #[test]
     fn test_parse() {
         let al = AdjLists::parse(TEST_CASE);
-        println!("{}", al.find_cut());
+        assert_eq!(al.find_cut().unwrap(), 54);
+    }
+
+    #[test]
+    fn test_min_cut() {
+        let al = AdjLists::parse(TEST_CASE);
+        let cut = al.min_cut();
+        let edges = cut
+            .edges
+            .iter()
+            .map(|&(a, b)| {
+                [al.names[a], al.names[b]]
+                    .into_iter()
+                    .sorted()
+                    .collect_tuple()
+            })
+            .sorted()
+            .collect_vec();
+        assert_eq!(
+            edges,
+            [
+                Some(("bvb", "cmg")),
+                Some(("hfx", "pzl")),
+                Some(("jqt", "nvd"))
+            ]
+        );
+
+        let mut sizes = cut.components().map(|c| c.len());
+        sizes.sort();
+        assert_eq!(sizes, [6, 9]);
+
+        // A ring can be cut with two wires, a 5-clique needs four.
+        assert!(AdjLists::parse("a: b\nb: c\nc: d\nd: a")
+            .find_cut()
+            .is_err());
+        let k5 = AdjLists::parse("a: b c d e\nb: c d e\nc: d e\nd: e");
+        assert_eq!(k5.min_cut().edges.len(), 4);
+        assert!(k5.find_cut().is_err());
+    }
+
+    #[test]
+    fn test_interning() {
+        let al = AdjLists::parse(TEST_CASE);
+        assert_eq!(al.len(), 15);
+        assert_eq!(al.edges().count(), 33);
+        assert_eq!(&al.names[..3], ["jqt", "rhn", "xhk"]);
+
+        let jqt = al.names.iter().position(|&name| name == "jqt").unwrap();
+        let neighbours = al
+            .neighbours(jqt)
+            .iter()
+            .map(|&b| al.names[b])
+            .sorted()
+            .collect_vec();
+        assert_eq!(neighbours, ["ntq", "nvd", "rhn", "xhk"]);
+
+        for arc in 0..al.targets.len() {
+            assert_eq!(al.reverse[al.reverse[arc]], arc);
+        }
+    }
+
+    #[test]
+    fn test_export() {
+        let al = AdjLists::parse(TEST_CASE);
+        let cut = al.min_cut();
+
+        let dot = al.to_dot(Some(&cut));
+        assert_eq!(dot.matches(" -- ").count(), 33);
+        assert_eq!(dot.matches("color=red").count(), 3);
+        assert!(dot.contains("\"pzl\" -- \"hfx\" [color=red, penwidth=3];"));
+
+        let graphml = al.to_graphml(Some(&cut));
+        assert_eq!(graphml.matches("<node ").count(), 15);
+        assert_eq!(graphml.matches("<edge ").count(), 33);
+        assert_eq!(graphml.matches("<data key=\"cut\">true</data>").count(), 3);
+        assert_eq!(
+            graphml.matches(COMPONENT_COLORS[0]).count()
+                + graphml.matches(COMPONENT_COLORS[1]).count(),
+            15
+        );
     }
 }
 
@@ -0,0 +1,254 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{bail, Context, Result};
+
+/// The other implementations living in this repository.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Port {
+    Nim,
+    Zig,
+}
+
+fn repo_root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .to_path_buf()
+}
+
+impl Port {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Port::Nim => "Nim",
+            Port::Zig => "Zig",
+        }
+    }
+
+    pub fn source_path(&self, day: u8) -> PathBuf {
+        match self {
+            Port::Nim => repo_root().join(format!("nim/src/day{day:02}.nim")),
+            Port::Zig => repo_root().join(format!("zig/day{day:02}.zig")),
+        }
+    }
+
+    /// The port that has an implementation of `day`, if any.
+    pub fn for_day(day: u8) -> Option<Self> {
+        [Port::Nim, Port::Zig]
+            .into_iter()
+            .find(|port| port.source_path(day).exists())
+    }
+
+    /// The input file of `day` the port reads, relative to its working directory. Both fall
+    /// back to downloading the input with `.cookie` when it is missing.
+    pub fn input_path(&self, day: u8) -> PathBuf {
+        match self {
+            Port::Nim => PathBuf::from(format!("input/day{day:02}.txt")),
+            Port::Zig => PathBuf::from(format!("input/day{day}.txt")),
+        }
+    }
+
+    /// Runs the port on `input` and returns the answers it printed. It is run from a scratch
+    /// directory holding a copy of the input at [`Port::input_path`].
+    pub fn run(&self, day: u8, input: &str) -> Result<PortAnswers> {
+        let workdir = std::env::temp_dir().join(format!("aoc-compare-{}", std::process::id()));
+        let path = workdir.join(self.input_path(day));
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(path, input)?;
+
+        let result = self.spawn(day, &workdir);
+        std::fs::remove_dir_all(&workdir).ok();
+
+        result
+    }
+
+    fn spawn(&self, day: u8, workdir: &Path) -> Result<PortAnswers> {
+        let output = match self {
+            Port::Nim => {
+                // The runner discovers the days at compile time, relative to the nimble project.
+                let nim_dir = repo_root().join("nim");
+                let status = Command::new("nimble")
+                    .arg("build")
+                    .current_dir(&nim_dir)
+                    .status()
+                    .context("can't run nimble")?;
+                if !status.success() {
+                    bail!("nimble build failed");
+                }
+
+                Command::new(nim_dir.join("adventofcode2023"))
+                    .arg(day.to_string())
+                    .current_dir(workdir)
+                    .output()
+                    .context("can't run the Nim build")?
+            }
+            Port::Zig => Command::new("zig")
+                .arg("run")
+                .arg(self.source_path(day))
+                .current_dir(workdir)
+                .output()
+                .context("can't run zig")?,
+        };
+
+        // Nim prints the answers on stdout, Zig through `std.debug.print` on stderr.
+        let text = format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        if !output.status.success() {
+            bail!(
+                "{} exited with {}: {}",
+                self.name(),
+                output.status,
+                text.trim()
+            );
+        }
+
+        Ok(parse_output(&text))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PortAnswers {
+    pub part1: Option<String>,
+    pub part2: Option<String>,
+}
+
+fn strip_ansi(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip the CSI sequence up to and including its final letter.
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Finds the `Part N: answer` lines both runners print, ignoring colours and any debug output
+/// the solutions emit around them.
+pub fn parse_output(output: &str) -> PortAnswers {
+    let mut answers = PortAnswers::default();
+
+    for line in strip_ansi(output).lines() {
+        let Some(rest) = line.trim().strip_prefix("Part ") else {
+            continue;
+        };
+        let Some((part, answer)) = rest.split_once(':') else {
+            continue;
+        };
+        let answer = Some(answer.trim().to_string());
+
+        match part.trim() {
+            "1" => answers.part1 = answer,
+            "2" => answers.part2 = answer,
+            _ => {}
+        }
+    }
+
+    answers
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comparison {
+    pub part: u8,
+    pub rust: String,
+    pub port: Option<String>,
+}
+
+impl Comparison {
+    pub fn matches(&self) -> bool {
+        self.port.as_deref() == Some(self.rust.as_str())
+    }
+}
+
+pub fn compare(rust: (u64, u64), port: &PortAnswers) -> [Comparison; 2] {
+    [
+        Comparison {
+            part: 1,
+            rust: rust.0.to_string(),
+            port: port.part1.clone(),
+        },
+        Comparison {
+            part: 2,
+            rust: rust.1.to_string(),
+            port: port.part2.clone(),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_output() {
+        let nim = "Importing day12\nPart \x1b[32m1\x1b[0m: \x1b[33m7047\x1b[0m\n\
+                   Part \x1b[32m2\x1b[0m: \x1b[33m17391848518844\x1b[0m\n";
+        assert_eq!(
+            parse_output(nim),
+            PortAnswers {
+                part1: Some("7047".to_string()),
+                part2: Some("17391848518844".to_string()),
+            }
+        );
+
+        let zig = "Downloading day 21...\n(1, 2) -> 3\n\
+                   \x1b[32mPart 1\x1b[0m:\x1b[33m 3600\x1b[0m\n";
+        assert_eq!(
+            parse_output(zig),
+            PortAnswers {
+                part1: Some("3600".to_string()),
+                part2: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_input_path() {
+        assert_eq!(Port::Nim.input_path(7), PathBuf::from("input/day07.txt"));
+
+        // The Zig runner reads the input of the day its entry point passes to `runDay`.
+        for day in [15, 17, 20, 21] {
+            assert_eq!(Port::for_day(day), Some(Port::Zig));
+            assert_eq!(
+                Port::Zig.input_path(day),
+                PathBuf::from(format!("input/day{day}.txt"))
+            );
+
+            let source = std::fs::read_to_string(Port::Zig.source_path(day)).unwrap();
+            assert!(
+                source.contains(&format!("try runDay(Day, {day});")),
+                "zig/day{day}.zig doesn't run day {day}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let port = PortAnswers {
+            part1: Some("16".to_string()),
+            part2: Some("17".to_string()),
+        };
+        let [p1, p2] = compare((16, 18), &port);
+        assert!(p1.matches());
+        assert!(!p2.matches());
+
+        let [p1, _] = compare((16, 18), &PortAnswers::default());
+        assert!(!p1.matches());
+    }
+
+    #[test]
+    fn test_for_day() {
+        assert_eq!(Port::for_day(12), Some(Port::Nim));
+        assert_eq!(Port::for_day(21), Some(Port::Zig));
+        assert_eq!(Port::for_day(1), None);
+    }
+}
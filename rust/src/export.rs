@@ -0,0 +1,109 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+
+/// Where the files of an `--export` go, and the options it was given.
+#[derive(Debug, Clone, Default)]
+pub struct Export {
+    /// How far to run a simulation before exporting it, like button presses or garden steps.
+    pub steps: Option<u64>,
+    /// The directory to write the files in, standard output if `None`.
+    pub out_dir: Option<PathBuf>,
+    /// A file written by an earlier export to start from, if the export can.
+    pub from: Option<PathBuf>,
+}
+
+/// A file of an export, or standard output.
+#[derive(Debug)]
+pub enum ExportFile {
+    Stdout(io::Stdout),
+    File(File),
+}
+
+impl Write for ExportFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ExportFile::Stdout(out) => out.write(buf),
+            ExportFile::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ExportFile::Stdout(out) => out.flush(),
+            ExportFile::File(file) => file.flush(),
+        }
+    }
+}
+
+impl Export {
+    /// Opens the file `name` of the export. Without an output directory, the contents go to
+    /// standard output and the name to standard error, so that a single file can be piped.
+    pub fn create(&self, name: &str) -> Result<ExportFile> {
+        let Some(dir) = &self.out_dir else {
+            eprintln!("==> {name} <==");
+            return Ok(ExportFile::Stdout(io::stdout()));
+        };
+
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(name);
+        let file = File::create(&path).with_context(|| format!("can't create {path:?}"))?;
+        eprintln!("writing {}", path.display());
+        Ok(ExportFile::File(file))
+    }
+
+    /// Writes the whole file `name` at once.
+    pub fn write(&self, name: &str, contents: &str) -> Result<()> {
+        let mut file = self.create(name)?;
+        file.write_all(contents.as_bytes())?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// The contents of the `--from` file, if one was given.
+    pub fn read_from(&self) -> Result<Option<String>> {
+        self.from
+            .as_ref()
+            .map(|path| {
+                std::fs::read_to_string(path).with_context(|| format!("can't read {path:?}"))
+            })
+            .transpose()
+    }
+
+    /// The number of steps to run for, `default` if `--steps` wasn't given.
+    pub fn steps_or(&self, default: u64) -> u64 {
+        self.steps.unwrap_or(default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write() {
+        let dir = std::env::temp_dir().join(format!("aoc-export-{}", std::process::id()));
+        let export = Export {
+            steps: None,
+            out_dir: Some(dir.join("nested")),
+            from: None,
+        };
+
+        export.write("day99.txt", "hello\n").unwrap();
+        let mut file = export.create("day99.dot").unwrap();
+        writeln!(file, "digraph {{}}").unwrap();
+        drop(file);
+
+        let read = |name| std::fs::read_to_string(dir.join("nested").join(name)).unwrap();
+        assert_eq!(read("day99.txt"), "hello\n");
+        assert_eq!(read("day99.dot"), "digraph {}\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(export.steps_or(1000), 1000);
+        assert_eq!(export.read_from().unwrap(), None);
+    }
+}
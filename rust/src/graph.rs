@@ -0,0 +1,201 @@
+use std::hash::Hash;
+
+use hashbrown::HashMap;
+
+/// A directed graph with weighted edges between densely indexed nodes, each labelled by the
+/// `N` it was added as, typically a position in a grid.
+#[derive(Debug, Clone)]
+pub struct WeightedGraph<N> {
+    nodes: Vec<N>,
+    edges: Vec<Vec<(usize, usize)>>,
+    index: HashMap<N, usize>,
+}
+
+impl<N: Copy + Eq + Hash> Default for WeightedGraph<N> {
+    fn default() -> Self {
+        Self {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+}
+
+impl<N: Copy + Eq + Hash> WeightedGraph<N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `node` if it isn't in the graph yet, and returns its index either way.
+    pub fn add_node(&mut self, node: N) -> usize {
+        *self.index.entry(node).or_insert_with(|| {
+            self.nodes.push(node);
+            self.edges.push(Vec::new());
+            self.nodes.len() - 1
+        })
+    }
+
+    pub fn add_edge(&mut self, from: N, to: N, weight: usize) {
+        let (from, to) = (self.add_node(from), self.add_node(to));
+        self.edges[from].push((to, weight));
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.edges.iter().map(Vec::len).sum()
+    }
+
+    pub fn index(&self, node: &N) -> Option<usize> {
+        self.index.get(node).copied()
+    }
+
+    pub fn node(&self, i: usize) -> N {
+        self.nodes[i]
+    }
+
+    pub fn nodes(&self) -> &[N] {
+        &self.nodes
+    }
+
+    /// The `(node, weight)` of the edges leaving node `i`.
+    pub fn neighbours(&self, i: usize) -> &[(usize, usize)] {
+        &self.edges[i]
+    }
+
+    /// Compresses a maze into the graph of its junctions, with the lengths of the corridors
+    /// between them as weights.
+    ///
+    /// `neighbours` gives the cells that can be stepped to from a cell, which may depend on
+    /// the direction, like slopes do. From every junction, each corridor is walked once until
+    /// it reaches the next junction. A corridor that dead-ends or turns back to where it
+    /// started adds no edge.
+    ///
+    /// # Panics
+    ///
+    /// If a corridor forks at a cell that isn't one of the `junctions`.
+    pub fn from_maze<I>(junctions: impl IntoIterator<Item = N>, neighbours: impl Fn(N) -> I) -> Self
+    where
+        I: IntoIterator<Item = N>,
+    {
+        let mut graph = Self::new();
+        for junction in junctions {
+            graph.add_node(junction);
+        }
+
+        for from in 0..graph.len() {
+            let start = graph.nodes[from];
+            for first in neighbours(start) {
+                let (mut prev, mut cell, mut length) = (start, first, 1);
+                while graph.index(&cell).is_none() {
+                    let mut ahead = neighbours(cell).into_iter().filter(|&n| n != prev);
+                    let Some(next) = ahead.next() else {
+                        break;
+                    };
+                    assert!(
+                        ahead.next().is_none(),
+                        "a corridor forks outside a junction"
+                    );
+                    (prev, cell, length) = (cell, next, length + 1);
+                }
+
+                if let Some(to) = graph.index(&cell) {
+                    if to != from {
+                        graph.edges[from].push((to, length));
+                    }
+                }
+            }
+        }
+
+        graph
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add() {
+        let mut graph = WeightedGraph::new();
+        assert!(graph.is_empty());
+        graph.add_edge('a', 'b', 3);
+        graph.add_edge('b', 'a', 3);
+        graph.add_edge('a', 'c', 1);
+
+        assert_eq!(graph.len(), 3);
+        assert_eq!(graph.edge_count(), 3);
+        assert_eq!(graph.add_node('b'), 1);
+        assert_eq!(graph.index(&'c'), Some(2));
+        assert_eq!(graph.neighbours(0), [(1, 3), (2, 1)]);
+        assert_eq!(graph.nodes(), ['a', 'b', 'c']);
+    }
+
+    #[test]
+    fn test_from_maze() {
+        let maze = &indoc::indoc! {"
+            #.#####
+            #...###
+            #.#.###
+            #.>...#
+            ###.#.#
+            ###...#
+            ###.###
+        "}
+        .lines()
+        .collect::<Vec<_>>();
+        let neighbours = |(x, y): (usize, usize)| {
+            let cells = [
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+            ];
+            cells
+                .into_iter()
+                .filter(|&(nx, ny)| ny < maze.len() && nx < maze[ny].len())
+                .filter(move |&(nx, ny)| match maze[ny].as_bytes()[nx] {
+                    b'#' => false,
+                    // The slope can only be walked down, to the east.
+                    b'>' => nx > x,
+                    _ => true,
+                })
+        };
+
+        let junctions = [(1, 0), (1, 1), (3, 3), (3, 5), (3, 6)];
+        let graph = WeightedGraph::from_maze(junctions, neighbours);
+        assert_eq!(graph.nodes(), junctions);
+
+        let edges = (0..graph.len())
+            .flat_map(|i| {
+                let graph = &graph;
+                graph
+                    .neighbours(i)
+                    .iter()
+                    .map(move |&(j, len)| (graph.node(i), graph.node(j), len))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            edges,
+            [
+                ((1, 0), (1, 1), 1),
+                ((1, 1), (1, 0), 1),
+                ((1, 1), (3, 3), 4),
+                ((1, 1), (3, 3), 4),
+                ((3, 3), (1, 1), 4),
+                ((3, 3), (3, 5), 2),
+                ((3, 3), (3, 5), 6),
+                ((3, 5), (3, 3), 2),
+                ((3, 5), (3, 6), 1),
+                ((3, 5), (3, 3), 6),
+                ((3, 6), (3, 5), 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use std::{collections::HashMap, fmt::Write, time::Duration};
+
+use anyhow::Result;
+use chrono::{TimeZone, Utc};
+use serde::Deserialize;
+
+use crate::profile::Profile;
+
+/// The site asks not to poll private leaderboards more often than this.
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Midnight EST on December 1st, when day 1 unlocks.
+const FIRST_UNLOCK_TS: i64 = 1701406800;
+
+#[derive(Debug, Deserialize)]
+pub struct Leaderboard {
+    pub owner_id: u64,
+    pub event: String,
+    pub members: HashMap<String, Member>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Member {
+    pub id: u64,
+    pub name: Option<String>,
+    pub stars: u32,
+    pub local_score: u64,
+    pub global_score: u64,
+    pub last_star_ts: i64,
+    pub completion_day_level: HashMap<u8, DayProgress>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DayProgress {
+    #[serde(rename = "1")]
+    pub part1: Star,
+    #[serde(rename = "2")]
+    pub part2: Option<Star>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Star {
+    pub get_star_ts: i64,
+    pub star_index: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StarEvent {
+    pub day: u8,
+    pub part: u8,
+    pub ts: i64,
+}
+
+fn unlock_ts(day: u8) -> i64 {
+    FIRST_UNLOCK_TS + (day as i64 - 1) * 86400
+}
+
+fn format_duration(secs: i64) -> String {
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h >= 24 {
+        format!(">{}d", h / 24)
+    } else if h > 0 {
+        format!("{h}h{m:02}m{s:02}s")
+    } else {
+        format!("{m}m{s:02}s")
+    }
+}
+
+impl Leaderboard {
+    pub fn parse(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Members by descending local score, ties broken by who got their last star first.
+    pub fn ranking(&self) -> Vec<&Member> {
+        let mut members = self.members.values().collect::<Vec<_>>();
+        members.sort_by_key(|m| (std::cmp::Reverse(m.local_score), m.last_star_ts, m.id));
+        members
+    }
+
+    /// Recomputes local scores from the star timestamps: for every star, the first member to
+    /// get it scores as many points as there are members, the second one less, and so on.
+    pub fn compute_local_scores(&self) -> HashMap<u64, u64> {
+        let member_count = self.members.len() as u64;
+        let mut scores = self
+            .members
+            .values()
+            .map(|m| (m.id, 0))
+            .collect::<HashMap<_, _>>();
+
+        let mut stars = HashMap::<(u8, u8), Vec<(i64, u64)>>::new();
+        for member in self.members.values() {
+            for event in member.timeline() {
+                stars
+                    .entry((event.day, event.part))
+                    .or_default()
+                    .push((event.ts, member.id));
+            }
+        }
+
+        for mut got in stars.into_values() {
+            got.sort_unstable();
+            for (rank, (_, id)) in got.into_iter().enumerate() {
+                *scores.get_mut(&id).unwrap() += member_count - rank as u64;
+            }
+        }
+
+        scores
+    }
+
+    pub fn render(&self) -> String {
+        let ranking = self.ranking();
+        let mut out = String::new();
+
+        writeln!(
+            out,
+            "{:>3}  {:>5}  {:<25}  Name",
+            "#", "Score", "1        10        20"
+        )
+        .unwrap();
+        for (rank, member) in ranking.iter().enumerate() {
+            writeln!(
+                out,
+                "{:>3}) {:>5}  {}  {} ({}\u{2605})",
+                rank + 1,
+                member.local_score,
+                member.calendar(),
+                member.display_name(),
+                member.stars
+            )
+            .unwrap();
+        }
+
+        writeln!(out).unwrap();
+        writeln!(out, "Time to part 1 after unlock / delta to part 2:").unwrap();
+        for member in ranking {
+            if member.completion_day_level.is_empty() {
+                continue;
+            }
+
+            writeln!(out, "  {}", member.display_name()).unwrap();
+            let mut days = member.completion_day_level.iter().collect::<Vec<_>>();
+            days.sort_by_key(|(day, _)| **day);
+            for (day, progress) in days {
+                let part1 = format_duration(progress.part1.get_star_ts - unlock_ts(*day));
+                let part2 = member
+                    .part2_delta(*day)
+                    .map(|delta| format!("+{}", format_duration(delta)))
+                    .unwrap_or_else(|| "-".to_string());
+                writeln!(out, "    Day {day:>2}  {part1:>10}  {part2:>11}").unwrap();
+            }
+        }
+
+        out
+    }
+}
+
+impl Member {
+    pub fn display_name(&self) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("(anonymous user #{})", self.id))
+    }
+
+    /// Every star the member got, in the order they got them.
+    pub fn timeline(&self) -> Vec<StarEvent> {
+        let mut events = self
+            .completion_day_level
+            .iter()
+            .flat_map(|(&day, progress)| {
+                [Some(&progress.part1), progress.part2.as_ref()]
+                    .into_iter()
+                    .zip(1..)
+                    .filter_map(move |(star, part)| {
+                        star.map(|star| StarEvent {
+                            day,
+                            part,
+                            ts: star.get_star_ts,
+                        })
+                    })
+            })
+            .collect::<Vec<_>>();
+        events.sort_by_key(|e| (e.ts, e.day, e.part));
+        events
+    }
+
+    /// Seconds between the two stars of a day, if both were obtained.
+    pub fn part2_delta(&self, day: u8) -> Option<i64> {
+        let progress = self.completion_day_level.get(&day)?;
+        Some(progress.part2.as_ref()?.get_star_ts - progress.part1.get_star_ts)
+    }
+
+    /// One character per day: `*` for both stars, `.` for part 1 only.
+    pub fn calendar(&self) -> String {
+        (1..=25)
+            .map(|day| match self.completion_day_level.get(&day) {
+                Some(DayProgress { part2: Some(_), .. }) => '*',
+                Some(_) => '.',
+                None => ' ',
+            })
+            .collect()
+    }
+
+    pub fn star_time(&self, day: u8, part: u8) -> Option<String> {
+        let progress = self.completion_day_level.get(&day)?;
+        let star = if part == 1 {
+            Some(&progress.part1)
+        } else {
+            progress.part2.as_ref()
+        }?;
+        let time = Utc.timestamp_opt(star.get_star_ts, 0).single()?;
+        Some(time.format("%Y-%m-%d %H:%M:%S").to_string())
+    }
+}
+
+pub fn fetch(profile: &Profile, id: u64) -> Result<Leaderboard> {
+    let body = profile.get_cached(
+        &format!("https://adventofcode.com/2023/leaderboard/private/view/{id}.json"),
+        &profile.cache_path(&format!("leaderboard-{id}.json")),
+        Some(REFRESH_INTERVAL),
+    )?;
+
+    Leaderboard::parse(&body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../fixtures/leaderboard.json");
+
+    #[test]
+    fn test_parse() {
+        let lb = Leaderboard::parse(FIXTURE).unwrap();
+        assert_eq!(lb.event, "2023");
+        assert_eq!(lb.members.len(), 3);
+
+        let names = lb
+            .ranking()
+            .into_iter()
+            .map(Member::display_name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["alice", "bob", "(anonymous user #3)"]);
+
+        for member in lb.members.values() {
+            assert_eq!(member.timeline().len(), member.stars as usize);
+        }
+    }
+
+    #[test]
+    fn test_local_scores() {
+        let lb = Leaderboard::parse(FIXTURE).unwrap();
+        let scores = lb.compute_local_scores();
+        for member in lb.members.values() {
+            assert_eq!(scores[&member.id], member.local_score);
+        }
+    }
+
+    #[test]
+    fn test_timeline() {
+        let lb = Leaderboard::parse(FIXTURE).unwrap();
+        let bob = &lb.members["2"];
+
+        assert_eq!(bob.calendar().trim_end(), "**.");
+        assert_eq!(bob.part2_delta(1), Some(3750));
+        assert_eq!(bob.part2_delta(3), None);
+        assert_eq!(bob.star_time(1, 1).unwrap(), "2023-12-01 05:04:10");
+
+        let days = bob
+            .timeline()
+            .iter()
+            .map(|e| (e.day, e.part))
+            .collect::<Vec<_>>();
+        assert_eq!(days, [(1, 1), (1, 2), (2, 1), (2, 2), (3, 1)]);
+
+        let rendered = lb.render();
+        assert!(rendered.contains("Day  1       4m10s    +1h02m30s"));
+    }
+}
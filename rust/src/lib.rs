@@ -1,65 +1,267 @@
-use std::path::PathBuf;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::Parser;
-use reqwest::Client;
+
+pub mod compare;
+pub mod export;
+pub mod graph;
+pub mod leaderboard;
+pub mod profile;
+pub mod rational;
+pub mod status;
+
+use compare::Port;
+use export::Export;
+use profile::Profile;
 
 pub trait AocSolution {
     const DAY: u8;
+    /// The extra views of the input that `--export` can produce, as `(name, description)`.
+    const EXPORTS: &'static [(&'static str, &'static str)] = &[];
     fn new() -> Self
     where
         Self: Sized;
     fn part1(&self, input: &str) -> u64;
     fn part2(&self, input: &str) -> u64;
+
+    /// Produces the view `name`, one of `EXPORTS`, of the input.
+    fn export(&self, _input: &str, name: &str, _export: &Export) -> Result<()> {
+        bail!("day {} has no {name} export", Self::DAY)
+    }
 }
 
 #[derive(Parser)]
 struct Cli {
-    /// Download input and run solution.
-    day: Option<u8>,
+    /// Run against this profile's input instead of the default one.
+    #[arg(long, conflicts_with = "all_profiles")]
+    profile: Option<String>,
+    /// Run against every profile's input and show the answers side by side.
+    #[arg(long)]
+    all_profiles: bool,
+    /// Diff the answers against the Nim or Zig implementation of the same day.
+    #[arg(long)]
+    compare: bool,
+    /// Produce one of the day's extra views of the input instead of solving it, `--export list`
+    /// shows them.
+    #[arg(long, value_name = "VIEW", conflicts_with_all = ["all_profiles", "compare"])]
+    export: Option<String>,
+    /// How far to run the simulation behind the export, like button presses or garden steps.
+    #[arg(long, requires = "export")]
+    steps: Option<u64>,
+    /// Write the export's files in this directory instead of printing them.
+    #[arg(long, value_name = "DIR", requires = "export")]
+    out_dir: Option<PathBuf>,
+    /// Start from a file written by an earlier export, like a saved state or a trace.
+    #[arg(long, value_name = "FILE", requires = "export")]
+    from: Option<PathBuf>,
 }
 
-async fn get_input(day: u8) -> Result<String> {
-    let path = PathBuf::from(format!("../input/day{day:02}.txt"));
+/// Runs a part, turning a panic into an error message so that one bad input doesn't
+/// take down the comparison with the others.
+fn solve(f: impl FnOnce() -> u64) -> Result<u64, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|e| {
+        e.downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| e.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "panicked".to_string())
+    })
+}
 
-    tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+fn run_profile<S: AocSolution>(profile: &Profile) -> Result<()> {
+    let input = profile.get_input(S::DAY)?;
+    let solution = S::new();
 
-    if tokio::fs::try_exists(&path).await? {
-        return Ok(tokio::fs::read_to_string(&path).await?);
-    }
+    let p1 = solution.part1(&input);
+    println!("\x1b[32;1mPart 1:\x1b[33;1m {p1}\x1b[0m");
+
+    let p2 = solution.part2(&input);
+    println!("\x1b[32;1mPart 2:\x1b[33;1m {p2}\x1b[0m");
+
+    profile.record_answers(S::DAY, p1, p2)?;
+
+    Ok(())
+}
+
+fn run_all_profiles<S: AocSolution>() -> Result<()> {
+    let solution = S::new();
+    let mut rows = Vec::new();
 
-    let cookie = include_str!("../../.cookie").trim();
+    // Panics are reported in the table, so keep the default hook from printing them too.
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
 
-    let body = Client::new()
-        .get(format!("https://adventofcode.com/2023/day/{day}/input"))
-        .header("Cookie", cookie)
-        .send()
-        .await?
-        .text()
-        .await?;
+    for profile in Profile::all()? {
+        let row = match profile.get_input(S::DAY) {
+            Ok(input) => {
+                let p1 = solve(|| solution.part1(&input));
+                let p2 = solve(|| solution.part2(&input));
+                if let (Ok(p1), Ok(p2)) = (&p1, &p2) {
+                    if let Err(e) = profile.record_answers(S::DAY, *p1, *p2) {
+                        eprintln!("profile {}: can't record answers: {e}", profile.name);
+                    }
+                }
 
-    tokio::fs::write(path, &body).await?;
+                let show = |r: Result<u64, String>| match r {
+                    Ok(v) => v.to_string(),
+                    Err(e) => format!("error: {e}"),
+                };
+                [profile.name.clone(), show(p1), show(p2)]
+            }
+            Err(e) => [
+                profile.name.clone(),
+                format!("no input: {e}"),
+                String::new(),
+            ],
+        };
+        rows.push(row);
+    }
 
-    Ok(body)
+    panic::set_hook(hook);
+
+    print!("{}", format_table(&["Profile", "Part 1", "Part 2"], &rows));
+
+    Ok(())
 }
 
-pub async fn run_solution<S: AocSolution>() {
-    let input = get_input(S::DAY).await.unwrap();
+fn run_compare<S: AocSolution>(profiles: &[Profile]) -> Result<()> {
+    let Some(port) = Port::for_day(S::DAY) else {
+        bail!("day {} has no Nim or Zig implementation", S::DAY);
+    };
+
     let solution = S::new();
+    let mut rows = Vec::new();
+    let mut mismatches = 0;
 
-    let p1 = solution.part1(&input);
-    println!("\x1b[32;1mPart 1:\x1b[33;1m {p1}\x1b[0m");
+    for profile in profiles {
+        let input = profile.get_input(S::DAY)?;
+        let rust = (solution.part1(&input), solution.part2(&input));
+        let port_answers = port.run(S::DAY, &input)?;
 
-    let p2 = solution.part2(&input);
-    println!("\x1b[32;1mPart 2:\x1b[33;1m {p2}\x1b[0m");
+        for cmp in compare::compare(rust, &port_answers) {
+            if !cmp.matches() {
+                mismatches += 1;
+            }
+            rows.push([
+                profile.name.clone(),
+                cmp.part.to_string(),
+                cmp.rust.clone(),
+                cmp.port.clone().unwrap_or_else(|| "-".to_string()),
+                if cmp.matches() { "ok" } else { "MISMATCH" }.to_string(),
+            ]);
+        }
+    }
+
+    print!(
+        "{}",
+        format_table(&["Profile", "Part", "Rust", port.name(), ""], &rows)
+    );
+
+    if mismatches > 0 {
+        bail!(
+            "{mismatches} answer(s) differ between Rust and {}",
+            port.name()
+        );
+    }
+
+    Ok(())
+}
+
+fn run_export<S: AocSolution>(profile: &Profile, name: &str, export: &Export) -> Result<()> {
+    let views = S::EXPORTS
+        .iter()
+        .map(|(name, description)| format!("  {name:<12} {description}"))
+        .collect::<Vec<_>>();
+
+    if views.is_empty() {
+        bail!("day {} has no exports", S::DAY);
+    }
+    if name == "list" {
+        println!("day {} exports:\n{}", S::DAY, views.join("\n"));
+        return Ok(());
+    }
+    if !S::EXPORTS.iter().any(|(view, _)| *view == name) {
+        bail!(
+            "day {} has no {name} export, it has:\n{}",
+            S::DAY,
+            views.join("\n")
+        );
+    }
+
+    let input = profile.get_input(S::DAY)?;
+    S::new().export(&input, name, export)
+}
+
+pub fn format_table<const N: usize>(header: &[&str; N], rows: &[[String; N]]) -> String {
+    let mut widths = header.map(str::len);
+    for row in rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.len());
+        }
+    }
+
+    let format_row = |cells: &[&str]| {
+        let line = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, w)| format!("{cell:<w$}"))
+            .collect::<Vec<_>>()
+            .join(" | ");
+        format!("{}\n", line.trim_end())
+    };
+
+    let separator = widths.map(|w| "-".repeat(w));
+
+    let mut out = format_row(header);
+    out += &format_row(&separator.each_ref().map(String::as_str));
+    for row in rows {
+        out += &format_row(&row.each_ref().map(String::as_str));
+    }
+    out
+}
+
+fn run<S: AocSolution>(cli: Cli) -> Result<()> {
+    let profile = match cli.profile.as_deref() {
+        Some(name) => Profile::named(name)?,
+        None => Profile::default_profile(),
+    };
+
+    if let Some(name) = &cli.export {
+        let export = Export {
+            steps: cli.steps,
+            out_dir: cli.out_dir.clone(),
+            from: cli.from.clone(),
+        };
+        run_export::<S>(&profile, name, &export)
+    } else if cli.compare {
+        let profiles = if cli.all_profiles {
+            Profile::all()?
+        } else {
+            vec![profile]
+        };
+        run_compare::<S>(&profiles)
+    } else if cli.all_profiles {
+        run_all_profiles::<S>()
+    } else {
+        run_profile::<S>(&profile)
+    }
+}
+
+pub fn run_solution<S: AocSolution>() {
+    if let Err(e) = run::<S>(Cli::parse()) {
+        eprintln!("\x1b[31;1mError:\x1b[0m {e:#}");
+        std::process::exit(1);
+    }
 }
 
 #[macro_export]
 macro_rules! run {
     ($sol:ty) => {
-        #[tokio::main]
-        async fn main() {
-            adventofcode2023::run_solution::<$sol>().await;
+        fn main() {
+            adventofcode2023::run_solution::<$sol>();
         }
     };
 }
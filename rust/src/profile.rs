@@ -0,0 +1,248 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+
+const DEFAULT_PROFILE: &str = "default";
+const PROFILES_DIR: &str = "../profiles";
+
+/// A named account: its session cookie and the directory its inputs are cached in.
+///
+/// The default profile keeps the original layout (`../.cookie` and `../input/`);
+/// named profiles live in `../profiles/<name>/.cookie` and `../profiles/<name>/input/`.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    root: PathBuf,
+}
+
+impl Profile {
+    pub fn default_profile() -> Self {
+        Self {
+            name: DEFAULT_PROFILE.to_string(),
+            root: PathBuf::from(".."),
+        }
+    }
+
+    /// The profile called `name`, which has to be a plain directory name so that it can't
+    /// point the cookie and caches outside of `../profiles`.
+    pub fn named(name: &str) -> Result<Self> {
+        if name == DEFAULT_PROFILE {
+            return Ok(Self::default_profile());
+        }
+
+        if name.is_empty() || name == "." || name.contains("..") || name.contains(['/', '\\']) {
+            bail!("invalid profile name {name:?}: it must be a plain directory name");
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            root: PathBuf::from(PROFILES_DIR).join(name),
+        })
+    }
+
+    /// Default profile first, then every directory in `../profiles` in name order.
+    pub fn all() -> Result<Vec<Self>> {
+        Self::all_in(Path::new(PROFILES_DIR))
+    }
+
+    /// The profiles of [`Profile::all`], with their directories listed from `dir`. A directory
+    /// whose name isn't a valid profile name is skipped with a warning, so that it can't keep
+    /// the other profiles from running.
+    fn all_in(dir: &Path) -> Result<Vec<Self>> {
+        let mut profiles = vec![Self::default_profile()];
+
+        if !dir.exists() {
+            return Ok(profiles);
+        }
+
+        let mut names = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| name != DEFAULT_PROFILE)
+            .collect::<Vec<_>>();
+        names.sort();
+
+        for name in names {
+            match Self::named(&name) {
+                Ok(profile) => profiles.push(profile),
+                Err(err) => eprintln!("warning: skipping {}: {err}", dir.join(&name).display()),
+            }
+        }
+
+        Ok(profiles)
+    }
+
+    pub fn cookie_path(&self) -> PathBuf {
+        self.root.join(".cookie")
+    }
+
+    pub fn cache_dir(&self) -> PathBuf {
+        self.root.join("input")
+    }
+
+    pub fn input_path(&self, day: u8) -> PathBuf {
+        self.cache_dir().join(format!("day{day:02}.txt"))
+    }
+
+    pub fn answers_path(&self, day: u8) -> PathBuf {
+        self.root.join("answers").join(format!("day{day:02}.txt"))
+    }
+
+    /// Where responses other than puzzle inputs (leaderboards, pages) are cached.
+    pub fn cache_path(&self, file_name: &str) -> PathBuf {
+        self.root.join("cache").join(file_name)
+    }
+
+    /// Stores the answers of the last run of a day, one part per line.
+    pub fn record_answers(&self, day: u8, p1: u64, p2: u64) -> Result<()> {
+        let path = self.answers_path(day);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(path, format!("{p1}\n{p2}\n"))?;
+        Ok(())
+    }
+
+    pub fn recorded_answers(&self, day: u8) -> Option<(u64, u64)> {
+        let answers = std::fs::read_to_string(self.answers_path(day)).ok()?;
+        let mut lines = answers.lines().map(|line| line.trim().parse().ok());
+        Some((lines.next()??, lines.next()??))
+    }
+
+    pub fn cookie(&self) -> Result<String> {
+        let path = self.cookie_path();
+        let cookie = std::fs::read_to_string(&path)
+            .with_context(|| format!("profile {}: can't read {}", self.name, path.display()))?;
+        let cookie = cookie.trim();
+
+        if cookie.is_empty() {
+            bail!("profile {}: {} is empty", self.name, path.display());
+        }
+
+        // Accept both the raw token and the full `session=...` header value.
+        if cookie.starts_with("session=") {
+            Ok(cookie.to_string())
+        } else {
+            Ok(format!("session={cookie}"))
+        }
+    }
+
+    #[cfg(feature = "fetch")]
+    pub fn get(&self, url: &str) -> Result<String> {
+        let cookie = self.cookie()?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        runtime.block_on(async {
+            let body = reqwest::Client::new()
+                .get(url)
+                .header("Cookie", cookie)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+
+            Ok(body)
+        })
+    }
+
+    #[cfg(not(feature = "fetch"))]
+    pub fn get(&self, url: &str) -> Result<String> {
+        bail!(
+            "profile {}: {url} is not cached and networking is disabled (build with `--features fetch`)",
+            self.name
+        )
+    }
+
+    /// Returns the cached body at `path` if it is younger than `max_age` (or at all, when
+    /// `max_age` is `None` or networking is disabled), otherwise fetches `url` and refreshes
+    /// the cache.
+    pub fn get_cached(&self, url: &str, path: &Path, max_age: Option<Duration>) -> Result<String> {
+        std::fs::create_dir_all(path.parent().unwrap())?;
+
+        if path.exists() {
+            let age = std::fs::metadata(path)?
+                .modified()?
+                .elapsed()
+                .unwrap_or_default();
+
+            let fresh = max_age.map(|max_age| age < max_age).unwrap_or(true);
+            if fresh || !cfg!(feature = "fetch") {
+                return Ok(std::fs::read_to_string(path)?);
+            }
+        }
+
+        let body = self.get(url)?;
+
+        std::fs::write(path, &body)?;
+
+        Ok(body)
+    }
+
+    pub fn get_input(&self, day: u8) -> Result<String> {
+        self.get_cached(
+            &format!("https://adventofcode.com/2023/day/{day}/input"),
+            &self.input_path(day),
+            None,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths() {
+        let default = Profile::default_profile();
+        assert_eq!(default.input_path(7), PathBuf::from("../input/day07.txt"));
+        assert_eq!(default.cookie_path(), PathBuf::from("../.cookie"));
+
+        let alice = Profile::named("alice").unwrap();
+        assert_eq!(
+            alice.input_path(21),
+            PathBuf::from("../profiles/alice/input/day21.txt")
+        );
+        assert_eq!(
+            alice.cookie_path(),
+            PathBuf::from("../profiles/alice/.cookie")
+        );
+
+        assert_eq!(Profile::named("default").unwrap().root, default.root);
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        for name in [
+            "",
+            ".",
+            "..",
+            "../../somewhere",
+            "a/b",
+            "a\\b",
+            "alice/..",
+            "..alice",
+        ] {
+            assert!(Profile::named(name).is_err(), "{name:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn test_all_skips_invalid_names() {
+        let dir = std::env::temp_dir().join(format!("aoc-profiles-{}", std::process::id()));
+        for name in ["bob", "alice", "a..b", "default"] {
+            std::fs::create_dir_all(dir.join(name)).unwrap();
+        }
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let profiles = Profile::all_in(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let names = profiles.iter().map(|p| p.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["default", "alice", "bob"]);
+    }
+}
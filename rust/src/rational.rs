@@ -0,0 +1,286 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    ops::{Add, Div, Mul, Neg, Sub},
+};
+
+/// An exact fraction over `i128`, always kept reduced with a positive denominator.
+///
+/// Arithmetic panics on overflow instead of wrapping, so a wrong answer is never silently
+/// produced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rational {
+    num: i128,
+    den: i128,
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs()
+}
+
+fn checked(value: Option<i128>) -> i128 {
+    value.expect("rational overflow")
+}
+
+impl Rational {
+    pub const ZERO: Self = Self { num: 0, den: 1 };
+    pub const ONE: Self = Self { num: 1, den: 1 };
+
+    pub fn new(num: i128, den: i128) -> Self {
+        assert!(den != 0, "rational with zero denominator");
+
+        let g = gcd(num, den);
+        let sign = den.signum();
+        Self {
+            num: sign * num / g,
+            den: sign * den / g,
+        }
+    }
+
+    pub fn numer(&self) -> i128 {
+        self.num
+    }
+
+    pub fn denom(&self) -> i128 {
+        self.den
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.num == 0
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.den == 1
+    }
+
+    /// The value as an integer, if it is one.
+    pub fn to_integer(&self) -> Option<i128> {
+        self.is_integer().then_some(self.num)
+    }
+
+    pub fn signum(&self) -> i128 {
+        self.num.signum()
+    }
+
+    pub fn abs(&self) -> Self {
+        Self {
+            num: self.num.abs(),
+            den: self.den,
+        }
+    }
+
+    pub fn recip(&self) -> Self {
+        Self::new(self.den, self.num)
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+}
+
+impl From<i128> for Rational {
+    fn from(value: i128) -> Self {
+        Self { num: value, den: 1 }
+    }
+}
+
+impl From<i64> for Rational {
+    fn from(value: i64) -> Self {
+        Self::from(value as i128)
+    }
+}
+
+impl Add for Rational {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        let g = gcd(self.den, rhs.den);
+        let (l, r) = (self.den / g, rhs.den / g);
+        let num = checked(
+            self.num
+                .checked_mul(r)
+                .zip(rhs.num.checked_mul(l))
+                .and_then(|(a, b)| a.checked_add(b)),
+        );
+        Self::new(num, checked(self.den.checked_mul(r)))
+    }
+}
+
+impl Sub for Rational {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        self + (-rhs)
+    }
+}
+
+impl Mul for Rational {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        // Cross-reduce first to keep the intermediate products small.
+        let g1 = gcd(self.num, rhs.den).max(1);
+        let g2 = gcd(rhs.num, self.den).max(1);
+        Self::new(
+            checked((self.num / g1).checked_mul(rhs.num / g2)),
+            checked((self.den / g2).checked_mul(rhs.den / g1)),
+        )
+    }
+}
+
+impl Div for Rational {
+    type Output = Self;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, rhs: Self) -> Self {
+        self * rhs.recip()
+    }
+}
+
+impl Neg for Rational {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self {
+            num: -self.num,
+            den: self.den,
+        }
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Compare the continued fraction expansions, which never overflows.
+        let (mut a, mut b, mut c, mut d) = (self.num, self.den, other.num, other.den);
+        loop {
+            let (q1, q2) = (a.div_euclid(b), c.div_euclid(d));
+            if q1 != q2 {
+                return q1.cmp(&q2);
+            }
+
+            let (r1, r2) = (a.rem_euclid(b), c.rem_euclid(d));
+            match (r1, r2) {
+                (0, 0) => return Ordering::Equal,
+                (0, _) => return Ordering::Less,
+                (_, 0) => return Ordering::Greater,
+                _ => {}
+            }
+
+            // Comparing r1/b with r2/d is the same as comparing d/r2 with b/r1.
+            (a, b, c, d) = (d, r2, b, r1);
+        }
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+/// Solves `a x = b` by Gauss-Jordan elimination. Returns `None` if `a` is singular.
+pub fn solve_linear(mut a: Vec<Vec<Rational>>, mut b: Vec<Rational>) -> Option<Vec<Rational>> {
+    let n = b.len();
+    assert!(a.len() == n && a.iter().all(|row| row.len() == n));
+
+    for col in 0..n {
+        // The smallest pivot tends to keep the numbers involved small.
+        let pivot = (col..n)
+            .filter(|&row| !a[row][col].is_zero())
+            .min_by_key(|&row| a[row][col].abs())?;
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let p = a[col][col];
+        for v in &mut a[col][col..] {
+            *v = *v / p;
+        }
+        b[col] = b[col] / p;
+
+        let (pivot_row, pivot_rhs) = (a[col].clone(), b[col]);
+        for (row, (a_row, b_row)) in a.iter_mut().zip(b.iter_mut()).enumerate() {
+            if row == col || a_row[col].is_zero() {
+                continue;
+            }
+
+            let f = a_row[col];
+            for (v, p) in a_row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *v = *v - f * *p;
+            }
+            *b_row = *b_row - f * pivot_rhs;
+        }
+    }
+
+    Some(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arithmetic() {
+        let a = Rational::new(6, -4);
+        assert_eq!((a.numer(), a.denom()), (-3, 2));
+
+        let b = Rational::new(1, 3);
+        assert_eq!(a + b, Rational::new(-7, 6));
+        assert_eq!(a - b, Rational::new(-11, 6));
+        assert_eq!(a * b, Rational::new(-1, 2));
+        assert_eq!(a / b, Rational::new(-9, 2));
+        assert_eq!((a / a).to_integer(), Some(1));
+        assert!(a < b && -a > b);
+        assert!(Rational::new(7, 5) < Rational::new(10, 7));
+        assert!(Rational::new(-7, 5) > Rational::new(-10, 7));
+        assert_eq!(
+            Rational::new(i128::MAX, 3).cmp(&Rational::new(i128::MAX - 1, 3)),
+            Ordering::Greater
+        );
+        assert_eq!(Rational::new(43, 3).to_string(), "43/3");
+    }
+
+    #[test]
+    fn test_large_values() {
+        // Positions around 4e14 times velocities around 1e3 stay exact.
+        let x = Rational::from(400_000_000_000_007i64);
+        let v = Rational::new(-313, 7);
+        let y = x * v / v;
+        assert_eq!(y, x);
+        assert_eq!(y.to_integer(), Some(400_000_000_000_007));
+    }
+
+    #[test]
+    fn test_solve_linear() {
+        let r = |v: i64| Rational::from(v);
+        let a = vec![
+            vec![r(2), r(1), r(-1)],
+            vec![r(-3), r(-1), r(2)],
+            vec![r(-2), r(1), r(2)],
+        ];
+        let b = vec![r(8), r(-11), r(-3)];
+        assert_eq!(solve_linear(a, b), Some(vec![r(2), r(3), r(-1)]));
+
+        let singular = vec![vec![r(1), r(2)], vec![r(2), r(4)]];
+        assert_eq!(solve_linear(singular, vec![r(1), r(2)]), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "rational overflow")]
+    fn test_overflow() {
+        let x = Rational::from(i128::MAX / 2);
+        let _ = x * Rational::from(3i64);
+    }
+}
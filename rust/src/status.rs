@@ -0,0 +1,225 @@
+use std::{collections::BTreeMap, path::PathBuf, time::Duration};
+
+use anyhow::Result;
+
+use crate::{format_table, profile::Profile};
+
+/// The calendar only changes when a star is obtained, no need to hit the site more often.
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DayStatus {
+    pub day: u8,
+    pub stars: u8,
+    pub rust: bool,
+    /// Parts whose solver is the `0` placeholder, from [`PLACEHOLDER_PARTS`].
+    pub placeholders: Vec<u8>,
+    pub answers: Option<(u64, u64)>,
+}
+
+impl DayStatus {
+    /// Implemented parts whose recorded answer is 0 anyway. That can be a genuine answer, so it
+    /// only shows up in the notes and doesn't make the day incomplete: a part that is still
+    /// stubbed out belongs in [`PLACEHOLDER_PARTS`].
+    pub fn zero_answers(&self) -> Vec<u8> {
+        match self.answers {
+            Some((p1, p2)) => [(1, p1), (2, p2)]
+                .into_iter()
+                .filter(|&(part, answer)| answer == 0 && !self.placeholders.contains(&part))
+                .map(|(part, _)| part)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn notes(&self) -> Vec<String> {
+        let mut notes = Vec::new();
+
+        if !self.rust {
+            if self.stars > 0 {
+                notes.push("no Rust solver".to_string());
+            }
+            return notes;
+        }
+
+        if self.answers.is_none() {
+            notes.push("no recorded answers".to_string());
+        }
+
+        for part in &self.placeholders {
+            notes.push(format!("part {part} is a placeholder"));
+        }
+
+        for part in self.zero_answers() {
+            notes.push(format!("part {part} answered 0"));
+        }
+
+        notes
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.rust && self.answers.is_some() && self.placeholders.is_empty()
+    }
+}
+
+/// Extracts the number of stars per day from the event's calendar page. Days that are still
+/// locked don't appear; days that are unlocked but unsolved have zero stars.
+pub fn parse_calendar(html: &str) -> BTreeMap<u8, u8> {
+    // Unlocked days are links, locked ones are plain spans with the same class.
+    html.split("<a ")
+        .skip(1)
+        .filter_map(|chunk| {
+            let tag = &chunk[..chunk.find('>')?];
+            let classes = tag.split("class=\"").nth(1)?;
+            let mut classes = classes[..classes.find('"')?].split_whitespace();
+            let day = classes.next()?.strip_prefix("calendar-day")?.parse().ok()?;
+            let stars = classes
+                .map(|class| match class {
+                    "calendar-complete" => 1,
+                    "calendar-verycomplete" => 2,
+                    _ => 0,
+                })
+                .max()
+                .unwrap_or(0);
+
+            Some((day, stars))
+        })
+        .collect()
+}
+
+/// The `(day, part)` of the solvers that only return `0` for now.
+pub const PLACEHOLDER_PARTS: &[(u8, u8)] = &[(12, 1), (25, 2)];
+
+/// Days that have a `src/bin/dayNN.rs` solver.
+pub fn rust_solvers() -> Result<Vec<u8>> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src")
+        .join("bin");
+
+    let mut days = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("day")?
+                .strip_suffix(".rs")?
+                .parse()
+                .ok()
+        })
+        .collect::<Vec<_>>();
+    days.sort_unstable();
+
+    Ok(days)
+}
+
+pub fn merge(
+    calendar: &BTreeMap<u8, u8>,
+    solvers: &[u8],
+    answers: impl Fn(u8) -> Option<(u64, u64)>,
+) -> Vec<DayStatus> {
+    (1..=25)
+        .map(|day| {
+            let rust = solvers.contains(&day);
+            DayStatus {
+                day,
+                stars: calendar.get(&day).copied().unwrap_or(0),
+                rust,
+                placeholders: PLACEHOLDER_PARTS
+                    .iter()
+                    .filter(|&&(d, _)| rust && d == day)
+                    .map(|&(_, part)| part)
+                    .collect(),
+                answers: if rust { answers(day) } else { None },
+            }
+        })
+        .collect()
+}
+
+pub fn render(statuses: &[DayStatus]) -> String {
+    let rows = statuses
+        .iter()
+        .map(|status| {
+            let (p1, p2) = status
+                .answers
+                .map(|(p1, p2)| (p1.to_string(), p2.to_string()))
+                .unwrap_or_default();
+            [
+                status.day.to_string(),
+                "*".repeat(status.stars as usize),
+                if status.rust { "yes" } else { "" }.to_string(),
+                p1,
+                p2,
+                status.notes().join(", "),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let stars = statuses.iter().map(|s| s.stars as usize).sum::<usize>();
+    let complete = statuses.iter().filter(|s| s.is_complete()).count();
+
+    format!(
+        "{}\n{stars}/50 stars, {complete}/25 days fully solved in Rust\n",
+        format_table(
+            &["Day", "Stars", "Rust", "Part 1", "Part 2", "Notes"],
+            &rows
+        )
+    )
+}
+
+pub fn fetch_calendar(profile: &Profile) -> Result<BTreeMap<u8, u8>> {
+    let html = profile.get_cached(
+        "https://adventofcode.com/2023",
+        &profile.cache_path("calendar.html"),
+        Some(REFRESH_INTERVAL),
+    )?;
+
+    Ok(parse_calendar(&html))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../fixtures/calendar.html");
+
+    #[test]
+    fn test_parse_calendar() {
+        let calendar = parse_calendar(FIXTURE);
+        assert_eq!(
+            calendar.into_iter().collect::<Vec<_>>(),
+            [(1, 2), (2, 2), (3, 1), (4, 0), (12, 1), (24, 1), (25, 1)]
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let calendar = parse_calendar(FIXTURE);
+        let answers = |day| match day {
+            1 => Some((54632, 54019)),
+            12 => Some((0, 525152)),
+            24 => Some((0, 0)),
+            _ => None,
+        };
+        let statuses = merge(&calendar, &[1, 4, 12, 24, 25], answers);
+
+        assert_eq!(statuses.len(), 25);
+        assert!(statuses[0].is_complete());
+        assert_eq!(statuses[2].notes(), ["no Rust solver"]);
+        assert_eq!(statuses[11].placeholders, [1]);
+        assert_eq!(statuses[11].notes(), ["part 1 is a placeholder"]);
+        assert_eq!(
+            statuses[23].notes(),
+            ["part 1 answered 0", "part 2 answered 0"]
+        );
+        // Zero answers are only noted, they don't make the day incomplete.
+        assert!(statuses[23].is_complete());
+        assert_eq!(
+            statuses[24].notes(),
+            ["no recorded answers", "part 2 is a placeholder"]
+        );
+        assert!(!statuses[24].is_complete());
+        assert_eq!(statuses[3].notes(), ["no recorded answers"]);
+        assert!(statuses[4].notes().is_empty());
+    }
+}
@@ -283,5 +283,5 @@ const Day = struct {
 };
 
 pub fn main() !void {
-    try runDay(Day, 0);
+    try runDay(Day, 17);
 }
@@ -336,5 +336,5 @@ const Day = struct {
 };
 
 pub fn main() !void {
-    try runDay(Day, 0);
+    try runDay(Day, 20);
 }